@@ -0,0 +1,419 @@
+use crate::err::CliError;
+use crate::forge::{ApiRequest, ChangeKind, Forge, MergeRequest, Milestone, User};
+use crate::render::MrLinks;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Wraps a forge so merge requests are only fetched once per `ttl`, and group members once per
+/// `group_ttl`. They're kept as one json file per merge request, under a directory per base url
+/// and project, and one per group.
+pub struct CachedForge {
+    forge: Box<dyn Forge>,
+    dir: PathBuf,
+    groups_dir: PathBuf,
+    /// How the forge's own project is named in the cache, merge requests from other projects go
+    /// under their path.
+    project: String,
+    ttl: Duration,
+    group_ttl: Duration,
+    /// Fetch everything again, and cache what comes back.
+    refresh: bool,
+}
+
+/// Everything about a merge request, including what the forges' json leaves out. Files written
+/// before a field was added fail to read, and are fetched again.
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedMr {
+    iid: u64,
+    title: String,
+    description: String,
+    author: Option<CachedUser>,
+    labels: Vec<String>,
+    milestone: Option<String>,
+    target_branch: Option<String>,
+    draft: bool,
+    merged: bool,
+    merge_commit_sha: Option<String>,
+    squash_commit_sha: Option<String>,
+    project: Option<String>,
+    pull_request: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedUser {
+    id: u64,
+    name: String,
+    username: String,
+}
+
+impl From<&MergeRequest> for CachedMr {
+    fn from(mr: &MergeRequest) -> CachedMr {
+        CachedMr {
+            iid: mr.iid,
+            title: mr.title.clone(),
+            description: mr.description.clone(),
+            author: mr.author.as_ref().map(|author| CachedUser {
+                id: author.id,
+                name: author.name.clone(),
+                username: author.username.clone(),
+            }),
+            labels: mr.labels.clone(),
+            milestone: mr.milestone.as_ref().map(|m| m.title.clone()),
+            target_branch: mr.target_branch.clone(),
+            draft: mr.draft,
+            merged: mr.merged,
+            merge_commit_sha: mr.merge_commit_sha.clone(),
+            squash_commit_sha: mr.squash_commit_sha.clone(),
+            project: mr.project.clone(),
+            pull_request: mr.kind == ChangeKind::PullRequest,
+        }
+    }
+}
+
+impl From<CachedMr> for MergeRequest {
+    fn from(cached: CachedMr) -> MergeRequest {
+        MergeRequest {
+            iid: cached.iid,
+            title: cached.title,
+            description: cached.description,
+            author: cached.author.map(|author| User {
+                id: author.id,
+                name: author.name,
+                username: author.username,
+            }),
+            labels: cached.labels,
+            milestone: cached.milestone.map(|title| Milestone { title }),
+            target_branch: cached.target_branch,
+            draft: cached.draft,
+            merged: cached.merged,
+            merge_commit_sha: cached.merge_commit_sha,
+            squash_commit_sha: cached.squash_commit_sha,
+            project: cached.project,
+            kind: if cached.pull_request {
+                ChangeKind::PullRequest
+            } else {
+                ChangeKind::MergeRequest
+            },
+        }
+    }
+}
+
+/// `$XDG_CACHE_HOME/shippy`, or `~/.cache/shippy`.
+pub fn cache_dir() -> Option<PathBuf> {
+    let root = env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+
+    Some(root.join("shippy"))
+}
+
+/// Keeps urls and `group/project` paths to one safe path segment each. Anything but letters,
+/// digits, `-`, `_` and `.` is percent-encoded, `%` included, so different names never share a
+/// key.
+fn key(name: &str) -> String {
+    let mut key = String::with_capacity(name.len());
+    for byte in name.bytes() {
+        match byte {
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' | b'.' => key.push(byte as char),
+            _ => key.push_str(format!("%{:02X}", byte).as_str()),
+        }
+    }
+    key
+}
+
+impl CachedForge {
+    /// `dir` is the cache's root, e.g. [`cache_dir`]. `base_url` and `project` keep projects
+    /// apart in it.
+    pub fn new(
+        forge: Box<dyn Forge>,
+        dir: &Path,
+        base_url: &str,
+        project: &str,
+        ttl: Duration,
+        group_ttl: Duration,
+        refresh: bool,
+    ) -> CachedForge {
+        CachedForge {
+            forge,
+            dir: dir.join("merge_requests").join(key(base_url)),
+            groups_dir: dir.join("groups").join(key(base_url)),
+            project: project.to_string(),
+            ttl,
+            group_ttl,
+            refresh,
+        }
+    }
+
+    fn path(&self, project: Option<&str>, iid: u64) -> PathBuf {
+        self.dir
+            .join(key(project.unwrap_or(self.project.as_str())))
+            .join(format!("{}.json", iid))
+    }
+
+    /// Anything missing, older than `ttl` or unreadable is a miss.
+    fn read_fresh<T: DeserializeOwned>(&self, path: &Path, ttl: Duration) -> Option<T> {
+        let age = fs::metadata(path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())?;
+        if self.refresh || age >= ttl {
+            return None;
+        }
+
+        let json = fs::read_to_string(path).ok()?;
+        serde_json::from_str(json.as_str()).ok()
+    }
+
+    /// The cache only saves time, so failing to write to it isn't worth failing the run for.
+    fn write<T: Serialize>(&self, path: &Path, value: &T) {
+        let json = match serde_json::to_string(value) {
+            Ok(json) => json,
+            Err(_) => return,
+        };
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir).and_then(|_| fs::write(path, json));
+        }
+    }
+}
+
+impl Forge for CachedForge {
+    /// Returns merge requests in the order of `ids`, however many came from the cache.
+    fn get_mrs(
+        &self,
+        project: Option<&str>,
+        ids: Vec<u64>,
+    ) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        let mut mrs = Vec::new();
+        let mut missing = Vec::new();
+        for iid in &ids {
+            match self.read_fresh::<CachedMr>(self.path(project, *iid).as_path(), self.ttl) {
+                Some(mr) => mrs.push(MergeRequest::from(mr)),
+                None => missing.push(*iid),
+            }
+        }
+
+        if !missing.is_empty() {
+            for mr in self.forge.get_mrs(project, missing)? {
+                self.write(self.path(project, mr.iid).as_path(), &CachedMr::from(&mr));
+                mrs.push(mr);
+            }
+        }
+
+        mrs.sort_by_key(|mr| ids.iter().position(|iid| *iid == mr.iid));
+        Ok(mrs)
+    }
+
+    fn get_mrs_for_commit(&self, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        self.forge.get_mrs_for_commit(sha)
+    }
+
+    fn group_members(
+        &self,
+        group: &str,
+        subgroups: bool,
+    ) -> Result<Vec<String>, CliError<'static>> {
+        let name = if subgroups {
+            format!("{}+subgroups.json", key(group))
+        } else {
+            format!("{}.json", key(group))
+        };
+        let path = self.groups_dir.join(name);
+        if let Some(usernames) = self.read_fresh(path.as_path(), self.group_ttl) {
+            return Ok(usernames);
+        }
+
+        let usernames = self.forge.group_members(group, subgroups)?;
+        self.write(path.as_path(), &usernames);
+        Ok(usernames)
+    }
+
+    fn links(&self) -> Result<MrLinks, CliError<'static>> {
+        self.forge.links()
+    }
+
+    fn release_request(
+        &self,
+        tag: &str,
+        description: &str,
+    ) -> Result<ApiRequest, CliError<'static>> {
+        self.forge.release_request(tag, description)
+    }
+
+    fn send(&self, request: &ApiRequest) -> Result<(), CliError<'static>> {
+        self.forge.send(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::{key, CachedForge};
+    use crate::forge::Forge;
+    use crate::forge_helpers::forge_helpers::{fake_forge, Asked};
+    use crate::git_helpers::git_helpers::tmp_dir;
+    use crate::mr_helpers::mr_helpers::mr;
+    use std::cell::RefCell;
+    use std::path::Path;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    fn cached(dir: &Path, ttl: Duration, refresh: bool) -> (CachedForge, Rc<RefCell<Asked>>) {
+        cached_groups(dir, ttl, ttl, refresh)
+    }
+
+    fn cached_groups(
+        dir: &Path,
+        ttl: Duration,
+        group_ttl: Duration,
+        refresh: bool,
+    ) -> (CachedForge, Rc<RefCell<Asked>>) {
+        let forge = fake_forge()
+            .mrs(|iid| {
+                mr(iid)
+                    .title(&format!("PR {}", iid))
+                    .labels(&["bug"])
+                    .milestone("1.0")
+                    .target_branch("main")
+                    .draft()
+                    .merge_commit("abc123")
+                    .pull_request()
+            })
+            .group("platform", false, &["platform-lead"])
+            .group("platform/backend", false, &["backend-lead"])
+            .group("platform/backend", true, &["backend-lead", "backend-sub"])
+            .group("a+subgroups", false, &["a-lead"])
+            .group("a", true, &["a-lead", "a-sub"]);
+        let asked = forge.asked();
+        let cached = CachedForge::new(
+            Box::new(forge),
+            dir,
+            "https://github.example.com",
+            "owner/repo",
+            ttl,
+            group_ttl,
+            refresh,
+        );
+        (cached, asked)
+    }
+
+    const HOUR: Duration = Duration::from_secs(60 * 60);
+
+    #[test]
+    fn only_fetches_what_is_not_cached() {
+        let dir = tmp_dir();
+        let (first, _) = cached(&dir, HOUR, false);
+        first.get_mrs(None, vec![1, 2]).unwrap();
+
+        let (second, asked) = cached(&dir, HOUR, false);
+        let mrs = second.get_mrs(None, vec![3, 2, 1]).unwrap();
+
+        assert_eq!(asked.borrow().mrs, vec![3]);
+        assert_eq!(
+            mrs.iter().map(|mr| mr.iid).collect::<Vec<_>>(),
+            vec![3, 2, 1]
+        );
+        assert_eq!(mrs[1].to_string(), "PR 2 by Alice");
+        assert_eq!(mrs[1].labels, vec!["bug"]);
+        assert_eq!(mrs[1].reference(), "#2");
+        assert_eq!(mrs[1].milestone.as_ref().unwrap().title, "1.0");
+        assert_eq!(mrs[1].target_branch.as_deref(), Some("main"));
+        assert!(mrs[1].draft);
+        assert!(mrs[1].merged);
+        assert_eq!(mrs[1].merge_commit_sha.as_deref(), Some("abc123"));
+    }
+
+    #[test]
+    fn keeps_projects_apart() {
+        let dir = tmp_dir();
+        let (first, _) = cached(&dir, HOUR, false);
+        first.get_mrs(None, vec![1]).unwrap();
+
+        let (second, asked) = cached(&dir, HOUR, false);
+        let other = second.get_mrs(Some("owner/other"), vec![1]).unwrap();
+
+        assert_eq!(asked.borrow().mrs, vec![1]);
+        assert_eq!(other[0].reference(), "owner/other#1");
+
+        let (third, asked) = cached(&dir, HOUR, false);
+        third.get_mrs(Some("owner/other"), vec![1]).unwrap();
+        assert!(asked.borrow().mrs.is_empty());
+    }
+
+    #[test]
+    fn keys_never_collide() {
+        assert_eq!(
+            key("https://gitlab.example.com"),
+            "https%3A%2F%2Fgitlab.example.com"
+        );
+        assert_ne!(key("group/a_b"), key("group_a/b"));
+        assert_ne!(key("group/a"), key("group%2Fa"));
+
+        let dir = tmp_dir();
+        let (first, _) = cached(&dir, HOUR, false);
+        first.get_mrs(Some("group/a_b"), vec![1]).unwrap();
+        first.group_members("a+subgroups", false).unwrap();
+
+        let (second, asked) = cached(&dir, HOUR, false);
+        second.get_mrs(Some("group_a/b"), vec![1]).unwrap();
+        second.group_members("a", true).unwrap();
+        assert_eq!(asked.borrow().mrs, vec![1]);
+        assert_eq!(asked.borrow().groups, vec!["a+subgroups"]);
+    }
+
+    #[test]
+    fn refresh_and_expiry_fetch_again() {
+        let dir = tmp_dir();
+        let (first, _) = cached(&dir, HOUR, false);
+        first.get_mrs(None, vec![1]).unwrap();
+
+        let (refreshed, asked) = cached(&dir, HOUR, true);
+        refreshed.get_mrs(None, vec![1]).unwrap();
+        assert_eq!(asked.borrow().mrs, vec![1]);
+
+        let (expired, asked) = cached(&dir, Duration::from_secs(0), false);
+        expired.get_mrs(None, vec![1]).unwrap();
+        assert_eq!(asked.borrow().mrs, vec![1]);
+    }
+
+    #[test]
+    fn caches_group_members_with_and_without_subgroups() {
+        let dir = tmp_dir();
+        let (first, _) = cached(&dir, HOUR, false);
+        first.group_members("platform/backend", false).unwrap();
+
+        let (second, asked) = cached(&dir, HOUR, false);
+        assert_eq!(
+            second.group_members("platform/backend", false).unwrap(),
+            vec!["backend-lead"]
+        );
+        assert!(asked.borrow().groups.is_empty());
+
+        assert_eq!(
+            second.group_members("platform/backend", true).unwrap(),
+            vec!["backend-lead", "backend-sub"]
+        );
+        assert_eq!(asked.borrow().groups, vec!["platform/backend+subgroups"]);
+
+        let (refreshed, asked) = cached(&dir, HOUR, true);
+        refreshed.group_members("platform/backend", false).unwrap();
+        assert_eq!(asked.borrow().groups, vec!["platform/backend"]);
+    }
+
+    #[test]
+    fn group_members_expire_apart_from_merge_requests() {
+        let dir = tmp_dir();
+        let (first, _) = cached(&dir, HOUR, false);
+        first.get_mrs(None, vec![1]).unwrap();
+        first.group_members("platform", false).unwrap();
+
+        let (second, asked) = cached_groups(&dir, HOUR, Duration::from_secs(0), false);
+        second.get_mrs(None, vec![1]).unwrap();
+        second.group_members("platform", false).unwrap();
+        assert!(asked.borrow().mrs.is_empty());
+        assert_eq!(asked.borrow().groups, vec!["platform"]);
+    }
+}
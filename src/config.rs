@@ -0,0 +1,336 @@
+use crate::err::CliError;
+use crate::Config;
+use serde_yaml::{Mapping, Value};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The per repo config file, found in the working directory or one above it in the repo.
+pub const CONFIG_FILE_NAME: &str = "shippy.yml";
+/// Keys whose entries are merged across layers, each entry replaced whole. Everything else,
+/// like `api_token`, is replaced whole.
+const MERGED_KEYS: &[&str] = &["teams", "filter"];
+/// `SHIPPY_TAG_PATTERN` overrides `tag_pattern`, and so on.
+pub const ENV_PREFIX: &str = "SHIPPY_";
+/// The keys a `SHIPPY_*` variable can set, the ones with plain values. Other variables are left
+/// alone, so `SHIPPY_API_TOKEN` can hold a token for `api_token: {from: EnvVar}`.
+pub const ENV_KEYS: &[&str] = &[
+    "forge",
+    "base_url",
+    "project_id",
+    "tag_pattern",
+    "template",
+    "conventional_commits",
+    "cache_ttl_hours",
+    "group_cache_ttl_hours",
+];
+
+/// `$XDG_CONFIG_HOME/shippy/config.yml`, or `~/.config/shippy/config.yml`. For what's the same
+/// across repos, like tokens and teams.
+pub fn user_config_path() -> Option<PathBuf> {
+    let root = env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+
+    Some(root.join("shippy").join("config.yml"))
+}
+
+/// The nearest [`CONFIG_FILE_NAME`] from `cwd` up to `root`, the repo's working directory. Both
+/// are canonicalized first, so a symlink or `..` can't walk past `root`.
+pub fn find_repo_config(cwd: &Path, root: Option<&Path>) -> Option<PathBuf> {
+    let canonical = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let cwd = canonical(cwd);
+    let root = root.map(canonical);
+
+    for dir in cwd.ancestors() {
+        let path = dir.join(CONFIG_FILE_NAME);
+        if path.is_file() {
+            return Some(path);
+        }
+        if root.as_deref().is_none_or(|root| dir == root) {
+            break;
+        }
+    }
+    None
+}
+
+/// The config files to read, lowest precedence first: the user's, then the repo's, or `explicit`
+/// instead of the repo's when it's given.
+pub fn layers(
+    explicit: Option<&Path>,
+    cwd: &Path,
+    root: Option<&Path>,
+) -> Result<Vec<PathBuf>, CliError<'static>> {
+    let mut layers: Vec<PathBuf> = user_config_path()
+        .filter(|path| path.is_file())
+        .into_iter()
+        .collect();
+
+    match explicit {
+        Some(path) if !path.is_file() => {
+            return Err(CliError::String(format!(
+                "Could not find config file {}",
+                path.display()
+            )))
+        }
+        Some(path) => layers.push(path.to_path_buf()),
+        None => layers.extend(find_repo_config(cwd, root)),
+    }
+
+    Ok(layers)
+}
+
+/// Merges `layers` in order, then `SHIPPY_*` variables from `vars`, over the defaults. Later
+/// layers replace earlier ones key by key, and so do the entries under [`MERGED_KEYS`], so e.g.
+/// `teams` from the user's config and the repo's are combined, but a team in both is the repo's.
+/// Variables are read as yaml scalars, so `SHIPPY_CACHE_TTL_HOURS=0` is a number, but
+/// `SHIPPY_TAG_PATTERN=[0-9]+` is still a string.
+pub fn load<I>(layers: &[PathBuf], vars: I) -> Result<Config, CliError<'static>>
+where
+    I: IntoIterator<Item = (String, String)>,
+{
+    let mut merged = Mapping::new();
+
+    for path in layers {
+        let yaml = fs::read_to_string(path).map_err(|e| {
+            CliError::String(format!(
+                "Could not read config file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        // serde_yaml won't read an empty document.
+        if yaml.trim().is_empty() {
+            continue;
+        }
+        let layer: Value = serde_yaml::from_str(yaml.as_str()).map_err(|e| {
+            CliError::String(format!(
+                "Could not deserialize config file {}: {}",
+                path.display(),
+                e
+            ))
+        })?;
+        match layer {
+            Value::Mapping(layer) => merge(&mut merged, layer),
+            // A file of only comments is null, and shouldn't wipe out what's under it.
+            Value::Null => {}
+            _ => {
+                return Err(CliError::String(format!(
+                    "Could not deserialize config file {}: expected a mapping of keys",
+                    path.display()
+                )))
+            }
+        }
+    }
+
+    for (name, value) in vars {
+        let key = match name.strip_prefix(ENV_PREFIX) {
+            Some(key) => key.to_lowercase(),
+            None => continue,
+        };
+        if !ENV_KEYS.contains(&key.as_str()) {
+            continue;
+        }
+        let value = match serde_yaml::from_str(value.as_str()) {
+            Ok(scalar @ Value::Bool(_)) | Ok(scalar @ Value::Number(_)) => scalar,
+            _ => Value::String(value),
+        };
+        let mut layer = Mapping::new();
+        layer.insert(Value::String(key), value);
+        merge(&mut merged, layer);
+    }
+
+    serde_yaml::from_value(Value::Mapping(merged))
+        .map_err(|e| CliError::Yaml("Could not deserialize config", e))
+}
+
+fn merge(base: &mut Mapping, layer: Mapping) {
+    for (key, value) in layer {
+        let merged = key.as_str().is_some_and(|key| MERGED_KEYS.contains(&key));
+        match (base.get_mut(&key), value) {
+            // `teams:` with nothing under it shouldn't wipe out the teams under it either.
+            (_, Value::Null) => {}
+            (Some(Value::Mapping(existing)), Value::Mapping(entries)) if merged => {
+                for (name, entry) in entries {
+                    existing.insert(name, entry);
+                }
+            }
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::{find_repo_config, layers, load, CONFIG_FILE_NAME};
+    use crate::git_helpers::git_helpers::tmp_dir;
+    use crate::{ApiToken, ProjectId, Team};
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn write(path: PathBuf, yaml: &str) -> PathBuf {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, yaml).unwrap();
+        path
+    }
+
+    fn no_vars() -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    #[test]
+    fn later_layers_win_key_by_key() {
+        let dir = tmp_dir();
+        let user = write(
+            dir.join("user.yml"),
+            "teams:\n  A: [alice]\n  B: [bob]\ncache_ttl_hours: 1\n",
+        );
+        let repo = write(
+            dir.join("repo.yml"),
+            "base_url: https://gitlab.example.com\nteams:\n  B: [carol]\n",
+        );
+
+        let cfg = load(&[user, repo], no_vars()).unwrap();
+
+        assert_eq!(cfg.base_url, Some("https://gitlab.example.com".to_string()));
+        assert_eq!(cfg.teams["A"], Team::Members(vec!["alice".to_string()]));
+        assert_eq!(cfg.teams["B"], Team::Members(vec!["carol".to_string()]));
+        assert_eq!(cfg.cache_ttl_hours, 1);
+    }
+
+    #[test]
+    fn tagged_values_and_teams_are_replaced_whole() {
+        let dir = tmp_dir();
+        let user = write(
+            dir.join("user.yml"),
+            "api_token:\n  from: GitCredential\n  host: gitlab.example.com\n\
+             teams:\n  A:\n    group: platform\n    subgroups: true\n\
+             filter:\n  exclude: [draft]\n",
+        );
+        let repo = write(
+            dir.join("repo.yml"),
+            "api_token:\n  from: GitCredential\n\
+             teams:\n  A:\n    group: backend\n\
+             filter:\n  include: [label:bug]\n",
+        );
+
+        let cfg = load(&[user, repo], no_vars()).unwrap();
+
+        assert_eq!(cfg.api_token, ApiToken::GitCredential { host: None });
+        assert_eq!(
+            cfg.teams["A"],
+            Team::Group {
+                group: "backend".to_string(),
+                subgroups: false
+            }
+        );
+        assert_eq!(cfg.filter.include, vec!["label:bug"]);
+        assert_eq!(cfg.filter.exclude, vec!["draft"]);
+    }
+
+    #[test]
+    fn env_vars_override_single_keys() {
+        let dir = tmp_dir();
+        let repo = write(
+            dir.join("repo.yml"),
+            "project_id: 12\nconventional_commits: false\n",
+        );
+        let vars = vec![
+            ("SHIPPY_PROJECT_ID".to_string(), "group/project".to_string()),
+            (
+                "SHIPPY_CONVENTIONAL_COMMITS".to_string(),
+                "true".to_string(),
+            ),
+            ("SHIPPY_CACHE_TTL_HOURS".to_string(), "0".to_string()),
+            ("SHIPPY_TAG_PATTERN".to_string(), "[0-9]+".to_string()),
+            ("SHIPPY_API_TOKEN".to_string(), "glpat-secret".to_string()),
+            ("SHIPPY_TEAMS".to_string(), "not a mapping".to_string()),
+            ("PATH".to_string(), "/usr/bin".to_string()),
+        ];
+
+        let cfg = load(&[repo], vars).unwrap();
+
+        assert_eq!(
+            cfg.project_id,
+            Some(ProjectId::Path("group/project".to_string()))
+        );
+        assert!(cfg.conventional_commits);
+        assert_eq!(cfg.cache_ttl_hours, 0);
+        assert_eq!(cfg.tag_pattern, Some("[0-9]+".to_string()));
+        assert_eq!(cfg.api_token, ApiToken::default());
+        assert!(cfg.teams.is_empty());
+    }
+
+    #[test]
+    fn empty_files_and_no_files_are_defaults() {
+        let dir = tmp_dir();
+        let empty = write(dir.join("empty.yml"), "");
+
+        let cfg = load(&[empty], no_vars()).unwrap();
+
+        assert_eq!(cfg.base_url, None);
+        assert!(cfg.teams.is_empty());
+        assert_eq!(load(&[], no_vars()).unwrap().cache_ttl_hours, 168);
+    }
+
+    #[test]
+    fn finds_nearest_config_up_to_root() {
+        let root = tmp_dir();
+        let sub = root.join("a").join("b");
+        fs::create_dir_all(&sub).unwrap();
+
+        assert_eq!(find_repo_config(&sub, Some(&root)), None);
+
+        let at_root = write(root.join(CONFIG_FILE_NAME), "");
+        assert_eq!(
+            find_repo_config(&sub, Some(&root)),
+            fs::canonicalize(at_root).ok()
+        );
+
+        let nearer = write(root.join("a").join(CONFIG_FILE_NAME), "");
+        assert_eq!(
+            find_repo_config(&sub, Some(&root)),
+            fs::canonicalize(nearer).ok()
+        );
+    }
+
+    #[test]
+    fn does_not_look_above_root() {
+        let above = tmp_dir();
+        write(above.join(CONFIG_FILE_NAME), "");
+        let root = above.join("repo");
+        fs::create_dir_all(&root).unwrap();
+
+        assert_eq!(find_repo_config(&root, Some(&root)), None);
+        assert_eq!(find_repo_config(&root, None), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn does_not_look_above_root_through_a_symlink() {
+        let above = tmp_dir();
+        write(above.join(CONFIG_FILE_NAME), "");
+        let root = above.join("repo");
+        fs::create_dir_all(root.join("sub")).unwrap();
+        let link = above.join("link");
+        std::os::unix::fs::symlink(&root, &link).unwrap();
+
+        assert_eq!(find_repo_config(&link.join("sub"), Some(&root)), None);
+        assert_eq!(find_repo_config(&root.join("sub"), Some(&link)), None);
+    }
+
+    #[test]
+    fn explicit_config_must_exist() {
+        let dir = tmp_dir();
+
+        assert!(layers(Some(&dir.join("missing.yml")), &dir, Some(&dir)).is_err());
+
+        let explicit = write(dir.join("other.yml"), "");
+        write(dir.join(CONFIG_FILE_NAME), "");
+        let found = layers(Some(&explicit), &dir, Some(&dir)).unwrap();
+        assert_eq!(found.last(), Some(&explicit));
+    }
+}
@@ -0,0 +1,110 @@
+use git2::Oid;
+use regex::Regex;
+use std::fmt;
+
+lazy_static! {
+    static ref HEADER: Regex = Regex::new(
+        r"^(?P<type>\w+)(?:\((?P<scope>[^)]*)\))?(?P<breaking>!)?: (?P<description>.+)$"
+    )
+    .unwrap();
+    static ref BREAKING_FOOTER: Regex = Regex::new(r"(?m)^BREAKING[ -]CHANGE: ").unwrap();
+}
+
+/// A commit message in the https://www.conventionalcommits.org format, e.g.
+/// `fix(parser)!: reject empty tags`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConventionalCommit {
+    pub commit_type: String,
+    pub scope: Option<String>,
+    /// From a `!` after the type or scope, or a `BREAKING CHANGE:` footer.
+    pub breaking: bool,
+    pub description: String,
+}
+
+impl fmt::Display for ConventionalCommit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.commit_type.as_str())?;
+        if let Some(scope) = &self.scope {
+            write!(f, "({})", scope)?;
+        }
+        if self.breaking {
+            f.write_str("!")?;
+        }
+        write!(f, ": {}", self.description)
+    }
+}
+
+/// A commit that isn't tied to a merge request, but can stand in for one in the notes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommitNote {
+    pub id: Oid,
+    pub commit: ConventionalCommit,
+}
+
+/// Returns `None` unless the first line of `message` is a conventional commit header.
+pub fn parse(message: &str) -> Option<ConventionalCommit> {
+    let header = HEADER.captures(message.lines().next()?.trim_end())?;
+    let body = message.split_once('\n').map(|(_, body)| body).unwrap_or("");
+
+    Some(ConventionalCommit {
+        commit_type: header["type"].to_string(),
+        scope: header
+            .name("scope")
+            .map(|m| m.as_str().trim().to_string())
+            .filter(|s| !s.is_empty()),
+        breaking: header.name("breaking").is_some() || BREAKING_FOOTER.is_match(body),
+        description: header["description"].trim().to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conventional::{parse, ConventionalCommit};
+
+    #[test]
+    fn parses_type_and_description() {
+        assert_eq!(
+            parse("feat: add json output"),
+            Some(ConventionalCommit {
+                commit_type: "feat".to_string(),
+                scope: None,
+                breaking: false,
+                description: "add json output".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn parses_scope_and_breaking_marker() {
+        let commit = parse("fix(parser)!: reject empty tags\n\nMore detail").unwrap();
+
+        assert_eq!(commit.commit_type, "fix");
+        assert_eq!(commit.scope, Some("parser".to_string()));
+        assert!(commit.breaking);
+        assert_eq!(commit.description, "reject empty tags");
+    }
+
+    #[test]
+    fn breaking_change_footer_is_breaking() {
+        let commit =
+            parse("refactor: drop v1 api\n\nBREAKING CHANGE: v1 clients stop working").unwrap();
+        assert!(commit.breaking);
+
+        let commit = parse("refactor: drop v1 api\n\nBREAKING-CHANGE: v1 is gone").unwrap();
+        assert!(commit.breaking);
+    }
+
+    #[test]
+    fn displays_as_header() {
+        let header = "fix(parser)!: reject empty tags";
+
+        assert_eq!(parse(header).unwrap().to_string(), header);
+    }
+
+    #[test]
+    fn other_messages_are_not_conventional() {
+        assert_eq!(parse("Merge branch 'main' into feature"), None);
+        assert_eq!(parse("feat:missing space"), None);
+        assert_eq!(parse(""), None);
+    }
+}
@@ -9,6 +9,9 @@ pub enum CliError<'this> {
     Git(&'this str, git2::Error),
     Http(String, reqwest::Error),
     Yaml(&'this str, serde_yaml::Error),
+    Regex(String, regex::Error),
+    Json(&'this str, serde_json::Error),
+    Template(&'this str, Box<handlebars::TemplateRenderError>),
 }
 
 impl fmt::Display for CliError<'_> {
@@ -30,6 +33,18 @@ impl fmt::Display for CliError<'_> {
                 .write_str(msg)
                 .and(f.write_str(":\n"))
                 .and(yaml_err.fmt(f)),
+            CliError::Regex(msg, regex_err) => f
+                .write_str(msg)
+                .and(f.write_str(":\n"))
+                .and(regex_err.fmt(f)),
+            CliError::Json(msg, json_err) => f
+                .write_str(msg)
+                .and(f.write_str(":\n"))
+                .and(json_err.fmt(f)),
+            CliError::Template(msg, template_err) => f
+                .write_str(msg)
+                .and(f.write_str(":\n"))
+                .and(template_err.fmt(f)),
         }
     }
 }
@@ -0,0 +1,354 @@
+use crate::err::CliError;
+use crate::forge::{Forge, MergeRequest};
+use crate::Team;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::mem;
+
+/// The config's `filter` block, in the same `key:value` terms as `--include` and `--exclude`.
+#[derive(Debug, Default, PartialEq, Deserialize)]
+pub struct FilterConfig {
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// One thing merge requests can be included or excluded by, e.g. `label:chore`,
+/// `target_branch:main`, `draft` or `title:^Revert`.
+#[derive(Debug)]
+pub enum Term {
+    Author(String),
+    /// The team's name, and its members' usernames.
+    Team(String, Vec<String>),
+    Label(String),
+    Milestone(String),
+    TargetBranch(String),
+    Draft(bool),
+    Title(Regex),
+}
+
+/// A merge request the filters removed, and why.
+#[derive(Debug, PartialEq)]
+pub struct FilteredOut {
+    pub mr: MergeRequest,
+    pub reason: String,
+}
+
+/// Merge requests are kept when they match every kind of term that's included, any one of the
+/// terms of each kind, and none of the terms that are excluded. So `label:bug label:feature
+/// target_branch:main` keeps bugs and features that went into main.
+#[derive(Debug, Default)]
+pub struct Filter {
+    include: Vec<Term>,
+    exclude: Vec<Term>,
+}
+
+impl Term {
+    /// `team:` terms are looked up in `teams`, with `forge` for teams that are groups.
+    pub fn parse(
+        term: &str,
+        teams: &HashMap<String, Team>,
+        forge: Option<&dyn Forge>,
+    ) -> Result<Term, CliError<'static>> {
+        let (key, value) = term.split_once(':').unwrap_or((term, ""));
+        let value = value.to_string();
+
+        let parsed = match key {
+            "author" => Term::Author(value),
+            "label" => Term::Label(value),
+            "milestone" => Term::Milestone(value),
+            "target_branch" => Term::TargetBranch(value),
+            "draft" => match value.as_str() {
+                "" | "true" => Term::Draft(true),
+                "false" => Term::Draft(false),
+                _ => {
+                    return Err(CliError::String(format!(
+                        "draft filters are draft, draft:true or draft:false, not {}",
+                        term
+                    )))
+                }
+            },
+            "title" => Term::Title(
+                Regex::new(value.as_str())
+                    .map_err(|e| CliError::Regex(format!("Invalid title filter {}", term), e))?,
+            ),
+            "team" => {
+                let team = teams.get(value.as_str()).ok_or_else(|| {
+                    CliError::String(format!("No team called {} in the config", value))
+                })?;
+                let forge = forge.ok_or(CliError::Str(
+                    "Can't filter to a team offline, merge requests have no authors",
+                ))?;
+                let members = team.members(forge)?;
+                Term::Team(value, members)
+            }
+            _ => {
+                return Err(CliError::String(format!(
+                    "Unknown filter {}, filters are author, team, label, milestone, \
+                     target_branch, draft and title",
+                    term
+                )))
+            }
+        };
+
+        if forge.is_none() && !matches!(parsed, Term::Title(_)) {
+            return Err(CliError::String(format!(
+                "Can't filter by {} offline, merge requests only have what their commits say",
+                term
+            )));
+        }
+        Ok(parsed)
+    }
+
+    pub fn matches(&self, mr: &MergeRequest) -> bool {
+        let username = mr.author.as_ref().map(|author| author.username.as_str());
+        match self {
+            Term::Author(name) => username == Some(name.as_str()),
+            Term::Team(_, members) => username.is_some_and(|u| members.iter().any(|m| m == u)),
+            Term::Label(label) => mr.labels.contains(label),
+            Term::Milestone(title) => mr.milestone.as_ref().is_some_and(|m| &m.title == title),
+            Term::TargetBranch(branch) => mr.target_branch.as_ref() == Some(branch),
+            Term::Draft(draft) => mr.draft == *draft,
+            Term::Title(regex) => regex.is_match(mr.title.as_str()),
+        }
+    }
+}
+
+impl Display for Term {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Term::Author(name) => write!(f, "author:{}", name),
+            Term::Team(name, _) => write!(f, "team:{}", name),
+            Term::Label(label) => write!(f, "label:{}", label),
+            Term::Milestone(title) => write!(f, "milestone:{}", title),
+            Term::TargetBranch(branch) => write!(f, "target_branch:{}", branch),
+            Term::Draft(true) => f.write_str("draft"),
+            Term::Draft(false) => f.write_str("draft:false"),
+            Term::Title(regex) => write!(f, "title:{}", regex),
+        }
+    }
+}
+
+impl Filter {
+    pub fn new(
+        include: &[String],
+        exclude: &[String],
+        teams: &HashMap<String, Team>,
+        forge: Option<&dyn Forge>,
+    ) -> Result<Filter, CliError<'static>> {
+        let parse = |terms: &[String]| -> Result<Vec<Term>, CliError<'static>> {
+            terms
+                .iter()
+                .map(|term| Term::parse(term.as_str(), teams, forge))
+                .collect()
+        };
+
+        Ok(Filter {
+            include: parse(include)?,
+            exclude: parse(exclude)?,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Why `mr` is filtered out, if it is.
+    pub fn reject_reason(&self, mr: &MergeRequest) -> Option<String> {
+        if let Some(term) = self.exclude.iter().find(|term| term.matches(mr)) {
+            return Some(format!("excluded by {}", term));
+        }
+
+        for term in &self.include {
+            let same_kind: Vec<&Term> = self
+                .include
+                .iter()
+                .filter(|other| mem::discriminant(*other) == mem::discriminant(term))
+                .collect();
+            if !same_kind.iter().any(|term| term.matches(mr)) {
+                let names: Vec<String> = same_kind.iter().map(|term| term.to_string()).collect();
+                return Some(format!("not included by {}", names.join(" or ")));
+            }
+        }
+
+        None
+    }
+
+    /// Splits `mrs` into those that are kept and those that aren't, keeping their order.
+    pub fn apply(&self, mrs: Vec<MergeRequest>) -> (Vec<MergeRequest>, Vec<FilteredOut>) {
+        let mut kept = Vec::new();
+        let mut filtered_out = Vec::new();
+        for mr in mrs {
+            match self.reject_reason(&mr) {
+                Some(reason) => filtered_out.push(FilteredOut { mr, reason }),
+                None => kept.push(mr),
+            }
+        }
+        (kept, filtered_out)
+    }
+}
+
+impl Display for Filter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let terms = |terms: &[Term]| -> String {
+            terms
+                .iter()
+                .map(Term::to_string)
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+
+        match (self.include.is_empty(), self.exclude.is_empty()) {
+            (true, true) => f.write_str("nothing"),
+            (false, true) => write!(f, "including {}", terms(&self.include)),
+            (true, false) => write!(f, "excluding {}", terms(&self.exclude)),
+            (false, false) => write!(
+                f,
+                "including {}, excluding {}",
+                terms(&self.include),
+                terms(&self.exclude)
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::filter::{Filter, FilteredOut};
+    use crate::forge::{Forge, MergeRequest};
+    use crate::forge_helpers::forge_helpers::fake_forge;
+    use crate::mr_helpers::mr_helpers::mr;
+    use crate::Team;
+    use std::collections::HashMap;
+
+    fn teams() -> HashMap<String, Team> {
+        let mut teams = HashMap::new();
+        teams.insert("A".to_string(), Team::Members(vec!["alice".to_string()]));
+        teams.insert(
+            "platform".to_string(),
+            Team::Group {
+                group: "platform".to_string(),
+                subgroups: false,
+            },
+        );
+        teams
+    }
+
+    fn filter(include: &[&str], exclude: &[&str]) -> Filter {
+        let strings = |terms: &[&str]| terms.iter().map(|t| t.to_string()).collect::<Vec<_>>();
+        let forge = fake_forge().group("platform", false, &["bob"]);
+        Filter::new(&strings(include), &strings(exclude), &teams(), Some(&forge)).unwrap()
+    }
+
+    fn kept(filter: &Filter, mrs: Vec<MergeRequest>) -> Vec<u64> {
+        filter.apply(mrs).0.iter().map(|mr| mr.iid).collect()
+    }
+
+    #[test]
+    fn excludes_any_matching_term() {
+        let filter = filter(&[], &["label:chore", "label:dependencies"]);
+        let mrs = vec![
+            mr(1).labels(&["chore"]).build(),
+            mr(2).labels(&["bug"]).build(),
+            mr(3).labels(&["bug", "dependencies"]).build(),
+        ];
+
+        let (kept, filtered_out) = filter.apply(mrs);
+
+        assert_eq!(kept.iter().map(|mr| mr.iid).collect::<Vec<_>>(), vec![2]);
+        assert_eq!(
+            filtered_out
+                .iter()
+                .map(|f| f.reason.as_str())
+                .collect::<Vec<_>>(),
+            vec!["excluded by label:chore", "excluded by label:dependencies"]
+        );
+    }
+
+    #[test]
+    fn includes_need_one_term_of_every_kind() {
+        let filter = filter(&["label:bug", "label:feature", "target_branch:main"], &[]);
+        let mrs = vec![
+            mr(1).labels(&["bug"]).target_branch("main").build(),
+            mr(2).labels(&["feature"]).target_branch("main").build(),
+            mr(3).labels(&["feature"]).target_branch("develop").build(),
+            mr(4).labels(&["chore"]).target_branch("main").build(),
+        ];
+
+        let (kept, filtered_out) = filter.apply(mrs);
+
+        assert_eq!(kept.iter().map(|mr| mr.iid).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(
+            filtered_out,
+            vec![
+                FilteredOut {
+                    mr: mr(3).labels(&["feature"]).target_branch("develop").build(),
+                    reason: "not included by target_branch:main".to_string()
+                },
+                FilteredOut {
+                    mr: mr(4).labels(&["chore"]).target_branch("main").build(),
+                    reason: "not included by label:bug or label:feature".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn filters_by_author_and_teams() {
+        let mrs = || {
+            vec![
+                mr(1).author("alice").build(),
+                mr(2).author("bob").build(),
+                mr(3).author("carol").build(),
+            ]
+        };
+
+        assert_eq!(kept(&filter(&["team:A"], &[]), mrs()), vec![1]);
+        assert_eq!(kept(&filter(&["team:platform"], &[]), mrs()), vec![2]);
+        assert_eq!(
+            kept(&filter(&["team:A", "author:carol"], &[]), mrs()),
+            Vec::<u64>::new()
+        );
+        assert_eq!(kept(&filter(&[], &["author:carol"]), mrs()), vec![1, 2]);
+    }
+
+    #[test]
+    fn filters_by_milestone_draft_and_title() {
+        let mrs = vec![
+            mr(1).milestone("1.0").build(),
+            mr(2).milestone("1.0").draft().build(),
+            mr(3).title("Revert \"MR 1\"").build(),
+        ];
+
+        let (kept, _) = filter(&["milestone:1.0"], &["draft", "title:^Revert"]).apply(mrs);
+
+        assert_eq!(kept.iter().map(|mr| mr.iid).collect::<Vec<_>>(), vec![1]);
+    }
+
+    #[test]
+    fn rejects_bad_terms() {
+        let forge = fake_forge().group("platform", false, &["bob"]);
+        let bad = |term: &str, forge: Option<&dyn Forge>| {
+            Filter::new(&[term.to_string()], &[], &teams(), forge).is_err()
+        };
+
+        assert!(bad("colour:red", Some(&forge)));
+        assert!(bad("draft:maybe", Some(&forge)));
+        assert!(bad("title:(", Some(&forge)));
+        assert!(bad("team:B", Some(&forge)));
+        assert!(bad("label:bug", None));
+        assert!(!bad("title:^Fix", None));
+    }
+
+    #[test]
+    fn describes_itself() {
+        assert_eq!(
+            filter(&["team:A", "draft:false"], &["title:^Revert"]).to_string(),
+            "including team:A, draft:false, excluding title:^Revert"
+        );
+        assert!(filter(&[], &[]).is_empty());
+    }
+}
@@ -0,0 +1,278 @@
+use crate::err::CliError;
+use crate::git::DEFAULT_MR_PATTERN;
+use crate::render::MrLinks;
+use crate::{gitea, github};
+use reqwest::Method;
+use serde::{Deserialize, Deserializer};
+use std::fmt::{Display, Error, Formatter};
+
+/// Where the project is hosted.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Deserialize)]
+pub enum ForgeKind {
+    #[default]
+    GitLab,
+    GitHub,
+    /// Forgejo too, it keeps Gitea's api.
+    Gitea,
+}
+
+impl ForgeKind {
+    /// Regexes for the references this forge writes into merge commits, see
+    /// [`MrMatcher`](crate::MrMatcher).
+    pub fn default_mr_patterns(self) -> Vec<String> {
+        let patterns: &[&str] = match self {
+            ForgeKind::GitLab => &[DEFAULT_MR_PATTERN],
+            ForgeKind::GitHub => &[github::MERGE_PATTERN, github::SQUASH_PATTERN],
+            ForgeKind::Gitea => &[
+                gitea::MERGE_PATTERN,
+                gitea::REVIEWED_ON_PATTERN,
+                gitea::SQUASH_PATTERN,
+            ],
+        };
+
+        patterns.iter().map(|p| p.to_string()).collect()
+    }
+
+    /// Where the api is for a forge at `host`, e.g. `gitlab.example.com`, served over `scheme`.
+    pub fn api_url(self, scheme: &str, host: &str) -> String {
+        match self {
+            ForgeKind::GitHub if host == "github.com" => "https://api.github.com".to_string(),
+            ForgeKind::GitHub => format!("{}://{}/api/v3", scheme, host),
+            ForgeKind::GitLab | ForgeKind::Gitea => format!("{}://{}", scheme, host),
+        }
+    }
+
+    /// The host for an api at `api_host`, where people sign in and push to, so where credential
+    /// helpers keep their passwords. Only github.com's api is on a host of its own.
+    pub fn web_host(self, api_host: &str) -> &str {
+        match self {
+            ForgeKind::GitHub if api_host == "api.github.com" => "github.com",
+            _ => api_host,
+        }
+    }
+
+    pub fn change_kind(self) -> ChangeKind {
+        match self {
+            ForgeKind::GitLab => ChangeKind::MergeRequest,
+            ForgeKind::GitHub | ForgeKind::Gitea => ChangeKind::PullRequest,
+        }
+    }
+}
+
+/// What shippy needs from wherever the project is hosted. Merge requests and pull requests are
+/// both [`MergeRequest`]s here.
+pub trait Forge {
+    /// `project` is the path of another project on the same forge, or `None` for this one.
+    fn get_mrs(
+        &self,
+        project: Option<&str>,
+        ids: Vec<u64>,
+    ) -> Result<Vec<MergeRequest>, CliError<'static>>;
+
+    /// The merge requests the forge knows contain `sha`.
+    fn get_mrs_for_commit(&self, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>>;
+
+    /// Usernames of `group`'s members, for teams defined by a group, see
+    /// [`Team`](crate::Team). Only GitLab has groups.
+    fn group_members(
+        &self,
+        group: &str,
+        _subgroups: bool,
+    ) -> Result<Vec<String>, CliError<'static>> {
+        Err(CliError::String(format!(
+            "Can't find the members of group {}, only GitLab teams can be groups",
+            group
+        )))
+    }
+
+    fn links(&self) -> Result<MrLinks, CliError<'static>>;
+
+    /// Creates the release for `tag`, or updates it if it's already there.
+    fn release_request(
+        &self,
+        tag: &str,
+        description: &str,
+    ) -> Result<ApiRequest, CliError<'static>>;
+
+    fn send(&self, request: &ApiRequest) -> Result<(), CliError<'static>>;
+}
+
+/// A merge request or pull request. It's read straight from GitLab's json, so its fields are
+/// named like GitLab's.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct MergeRequest {
+    pub iid: u64,
+    pub(crate) title: String,
+    pub(crate) description: String,
+    /// `None` for merge requests only known from commit messages, see [`MrSource`](crate::MrSource).
+    pub author: Option<User>,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub milestone: Option<Milestone>,
+    /// The branch it merges into, `None` for merge requests only known from commit messages.
+    #[serde(default)]
+    pub target_branch: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+    /// Rather than still open, or closed without being merged.
+    #[serde(rename = "state", deserialize_with = "is_merged", default)]
+    pub merged: bool,
+    /// The commit merging it made, which may be its only commit after a fast-forward merge.
+    #[serde(default)]
+    pub merge_commit_sha: Option<String>,
+    /// The commit its changes were squashed into, GitLab only.
+    #[serde(default)]
+    pub squash_commit_sha: Option<String>,
+    /// The `group/project` path, for merge requests that come from another project.
+    #[serde(skip)]
+    pub project: Option<String>,
+    #[serde(skip)]
+    pub kind: ChangeKind,
+}
+
+/// Forges call their change requests different things, and refer to them differently.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ChangeKind {
+    /// GitLab's, referred to like `!12`.
+    #[default]
+    MergeRequest,
+    /// GitHub's, referred to like `#12`.
+    PullRequest,
+}
+
+fn is_merged<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    String::deserialize(deserializer).map(|state| state == "merged")
+}
+
+impl Display for MergeRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.write_str(self.title.as_str())?;
+        match &self.author {
+            Some(author) => write!(f, " by {}", author.name),
+            None => Ok(()),
+        }
+    }
+}
+
+impl MergeRequest {
+    /// How the forge refers to this merge request from the project's own pages, e.g. `!12` or
+    /// `group/other!12` on GitLab.
+    pub fn reference(&self) -> String {
+        let sigil = match self.kind {
+            ChangeKind::MergeRequest => '!',
+            ChangeKind::PullRequest => '#',
+        };
+
+        match &self.project {
+            Some(path) => format!("{}{}{}", path, sigil, self.iid),
+            None => format!("{}{}", sigil, self.iid),
+        }
+    }
+
+    /// The commits that merging it made, the ones that show it's in a branch.
+    pub fn merge_commits(&self) -> impl Iterator<Item = &str> {
+        self.merge_commit_sha
+            .iter()
+            .chain(self.squash_commit_sha.iter())
+            .map(String::as_str)
+    }
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Milestone {
+    pub title: String,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct User {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+    pub username: String,
+}
+
+/// A change to make through the api, kept apart from sending it so it can be shown for a dry run.
+#[derive(Debug, PartialEq)]
+pub struct ApiRequest {
+    pub method: Method,
+    pub url: String,
+    pub body: String,
+}
+
+impl Display for ApiRequest {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        write!(f, "{} {}\n\n{}", self.method, self.url, self.body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forge::ForgeKind;
+    use crate::git::associated_mrs;
+    use crate::git_helpers::git_helpers::{commit_with_message, tmp_repo};
+    use crate::MrRef;
+    use regex::Regex;
+
+    fn refs_in(kind: ForgeKind, message: &str) -> Vec<MrRef> {
+        let repo = tmp_repo();
+        let oid = commit_with_message(&repo, message).unwrap();
+        let commit = repo.find_commit(oid).unwrap();
+        let patterns: Vec<Regex> = kind
+            .default_mr_patterns()
+            .iter()
+            .map(|p| Regex::new(p).unwrap())
+            .collect();
+
+        associated_mrs(&commit, &patterns)
+    }
+
+    fn own(iid: u64) -> MrRef {
+        MrRef { project: None, iid }
+    }
+
+    #[test]
+    fn web_host_is_where_credentials_are_kept() {
+        assert_eq!(ForgeKind::GitHub.web_host("api.github.com"), "github.com");
+        assert_eq!(
+            ForgeKind::GitHub.web_host("github.example.com"),
+            "github.example.com"
+        );
+        assert_eq!(ForgeKind::GitLab.web_host("gitlab.com"), "gitlab.com");
+        assert_eq!(ForgeKind::Gitea.web_host("codeberg.org"), "codeberg.org");
+    }
+
+    #[test]
+    fn finds_github_merge_commits_and_squash_suffixes() {
+        let refs_in = |message| refs_in(ForgeKind::GitHub, message);
+
+        assert_eq!(
+            refs_in("Merge pull request #123 from alice/export\n\nAdd export"),
+            vec![own(123)]
+        );
+        assert_eq!(refs_in("Add export (#45)\n\n* wip (#3)"), vec![own(45)]);
+        assert_eq!(
+            refs_in("Merge pull request owner/other#9 from alice/export"),
+            vec![MrRef {
+                project: Some("owner/other".to_string()),
+                iid: 9
+            }]
+        );
+        assert_eq!(refs_in("Fix #12 properly"), vec![]);
+    }
+
+    #[test]
+    fn finds_gitea_merge_commits_trailers_and_squash_suffixes() {
+        let refs_in = |message| refs_in(ForgeKind::Gitea, message);
+
+        assert_eq!(
+            refs_in("Merge pull request 'Add export (v2)' (#123) from export into main"),
+            vec![own(123)]
+        );
+        assert_eq!(
+            refs_in("Add export\n\nReviewed-on: https://git.example.com/owner/repo/pulls/45\nReviewed-by: bob"),
+            vec![own(45)]
+        );
+        assert_eq!(refs_in("Add export (#67)\n\n* wip (#3)"), vec![own(67)]);
+        assert_eq!(refs_in("Fix #12 properly"), vec![]);
+    }
+}
@@ -0,0 +1,119 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+pub mod forge_helpers {
+    use crate::err::CliError;
+    use crate::forge::{ApiRequest, Forge, MergeRequest};
+    use crate::mr_helpers::mr_helpers::{mr, MrBuilder};
+    use crate::render::MrLinks;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    /// A [`Forge`] for tests, see [`fake_forge`].
+    pub struct FakeForge {
+        build: Box<dyn Fn(u64) -> MrBuilder>,
+        groups: HashMap<(String, bool), Vec<String>>,
+        commits: HashMap<String, Vec<u64>>,
+        asked: Rc<RefCell<Asked>>,
+    }
+
+    /// What a [`FakeForge`] was asked for, in order.
+    #[derive(Debug, Default)]
+    pub struct Asked {
+        pub mrs: Vec<u64>,
+        /// Group names, with `+subgroups` on the end when subgroups were asked for too.
+        pub groups: Vec<String>,
+        pub commits: Vec<String>,
+    }
+
+    /// Hands out [`mr`]s for any iid, in reverse order as forges don't keep to the order they're
+    /// asked in. It knows no groups or commits, and can't make releases.
+    pub fn fake_forge() -> FakeForge {
+        FakeForge {
+            build: Box::new(mr),
+            groups: HashMap::new(),
+            commits: HashMap::new(),
+            asked: Rc::new(RefCell::new(Asked::default())),
+        }
+    }
+
+    impl FakeForge {
+        /// Builds the merge requests it hands out from their iid. Ones from another project get
+        /// its path set afterwards.
+        pub fn mrs(mut self, build: impl Fn(u64) -> MrBuilder + 'static) -> FakeForge {
+            self.build = Box::new(build);
+            self
+        }
+
+        pub fn group(mut self, group: &str, subgroups: bool, members: &[&str]) -> FakeForge {
+            let members = members.iter().map(|m| m.to_string()).collect();
+            self.groups.insert((group.to_string(), subgroups), members);
+            self
+        }
+
+        /// The merge requests the forge says contain `sha`.
+        pub fn commit(mut self, sha: &str, iids: &[u64]) -> FakeForge {
+            self.commits.insert(sha.to_string(), iids.to_vec());
+            self
+        }
+
+        /// Stays readable once the forge is boxed up or wrapped.
+        pub fn asked(&self) -> Rc<RefCell<Asked>> {
+            self.asked.clone()
+        }
+    }
+
+    impl Forge for FakeForge {
+        fn get_mrs(
+            &self,
+            project: Option<&str>,
+            ids: Vec<u64>,
+        ) -> Result<Vec<MergeRequest>, CliError<'static>> {
+            self.asked.borrow_mut().mrs.extend(&ids);
+            Ok(ids
+                .iter()
+                .rev()
+                .map(|iid| match project {
+                    Some(project) => (self.build)(*iid).project(project).build(),
+                    None => (self.build)(*iid).build(),
+                })
+                .collect())
+        }
+
+        fn get_mrs_for_commit(&self, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>> {
+            self.asked.borrow_mut().commits.push(sha.to_string());
+            let iids = self.commits.get(sha).map(Vec::as_slice).unwrap_or(&[]);
+            Ok(iids.iter().map(|iid| (self.build)(*iid).build()).collect())
+        }
+
+        fn group_members(
+            &self,
+            group: &str,
+            subgroups: bool,
+        ) -> Result<Vec<String>, CliError<'static>> {
+            let asked_for = if subgroups {
+                format!("{}+subgroups", group)
+            } else {
+                group.to_string()
+            };
+            self.asked.borrow_mut().groups.push(asked_for);
+
+            self.groups
+                .get(&(group.to_string(), subgroups))
+                .cloned()
+                .ok_or_else(|| CliError::String(format!("FakeForge has no group {}", group)))
+        }
+
+        fn links(&self) -> Result<MrLinks, CliError<'static>> {
+            Ok(MrLinks::unlinked())
+        }
+
+        fn release_request(&self, _: &str, _: &str) -> Result<ApiRequest, CliError<'static>> {
+            Err(CliError::Str("FakeForge can't make releases"))
+        }
+
+        fn send(&self, _: &ApiRequest) -> Result<(), CliError<'static>> {
+            Err(CliError::Str("FakeForge can't make releases"))
+        }
+    }
+}
@@ -1,4 +1,5 @@
 #[cfg(test)]
+#[allow(clippy::module_inception)]
 pub mod git_helpers {
     use git2::{Commit, Error, Oid, ReferenceType, Repository, Tree};
     use rand::Rng;
@@ -68,7 +69,26 @@ pub mod git_helpers {
         )
     }
 
-    pub fn empty_tree(repo: &Repository) -> Result<Tree, Error> {
+    /// Commits on top of `parent` without moving HEAD, like a commit on another branch.
+    pub fn detached_commit(repo: &Repository, parent: Oid) -> Result<Oid, Error> {
+        let sig = repo.signature()?;
+        let tree = empty_tree(repo)?;
+        let parent = repo.find_commit(parent)?;
+
+        repo.commit(None, &sig, &sig, "A detached commit", &tree, &[&parent])
+    }
+
+    /// Merges `other` into HEAD, HEAD being the first parent.
+    pub fn merge_commit(repo: &Repository, other: Oid) -> Result<Oid, Error> {
+        let sig = repo.signature()?;
+        let tree = empty_tree(repo)?;
+        let head = peel_ref(repo, "HEAD")?;
+        let other = repo.find_commit(other)?;
+
+        repo.commit(Some("HEAD"), &sig, &sig, "A merge", &tree, &[&head, &other])
+    }
+
+    pub fn empty_tree(repo: &Repository) -> Result<Tree<'_>, Error> {
         let tree_id = {
             let mut idx = repo.index()?;
 
@@ -79,8 +99,7 @@ pub mod git_helpers {
 
     pub fn tmp_repo() -> Repository {
         let dir = tmp_dir();
-        let repo = Repository::init(dir).unwrap();
-        repo
+        Repository::init(dir).unwrap()
     }
 
     pub fn lightweight_tag(repo: &Repository, commit_id: Oid, name: &str) -> Result<Oid, Error> {
@@ -138,22 +157,21 @@ pub mod git_helpers {
 
         let commit = repo.find_commit(commit_id).unwrap();
         let tag_name = "the-tag";
-        let tag_id = repo
-            .tag(
-                tag_name,
-                &commit.into_object(),
-                &repo.signature().unwrap(),
-                "This is a tag",
-                false,
-            )
-            .unwrap();
+        repo.tag(
+            tag_name,
+            &commit.into_object(),
+            &repo.signature().unwrap(),
+            "This is a tag",
+            false,
+        )
+        .unwrap();
 
         let tags = repo.tag_names(Option::Some("the-*")).unwrap();
 
         assert_eq!(tags.len(), 1);
 
         let tag_refname = "refs/tags/".to_owned().add(tags.get(0).unwrap());
-        let tag_ref = repo.find_reference(tag_refname.as_str()).unwrap();
+        repo.find_reference(tag_refname.as_str()).unwrap();
     }
 
     #[test]
@@ -161,14 +179,14 @@ pub mod git_helpers {
         let repo = tmp_repo();
 
         let commit_id = initial_commit(&repo).unwrap();
-        lightweight_tag(&repo, commit_id, "the-tag");
+        lightweight_tag(&repo, commit_id, "the-tag").unwrap();
 
         let tags = repo.tag_names(Option::Some("the-*")).unwrap();
 
         assert_eq!(tags.len(), 1);
 
         let tag_refname = "refs/tags/".to_owned().add(tags.get(0).unwrap());
-        let tag_ref = repo.find_reference(tag_refname.as_str()).unwrap();
+        repo.find_reference(tag_refname.as_str()).unwrap();
     }
 
     #[test]
@@ -176,7 +194,7 @@ pub mod git_helpers {
         let repo = tmp_repo();
 
         let commit_id1 = initial_commit(&repo).unwrap();
-        let commit_id2 = empty_commit(&repo).unwrap();
+        empty_commit(&repo).unwrap();
         let commit_id3 = empty_commit(&repo).unwrap();
 
         let tag_id = lightweight_tag(&repo, commit_id1, "the-tag").unwrap();
@@ -201,11 +219,11 @@ pub mod git_helpers {
         let commit_id1 = initial_commit(&repo).unwrap();
         let commit_id2 = empty_commit(&repo).unwrap();
         let commit_id3 = empty_commit(&repo).unwrap();
-        let commit_id4 = empty_commit(&repo).unwrap();
-        let commit_id5 = empty_commit(&repo).unwrap();
+        empty_commit(&repo).unwrap();
+        empty_commit(&repo).unwrap();
 
         let tag_id = lightweight_tag(&repo, commit_id1, "the-tag").unwrap();
-        let commit = repo.find_commit(tag_id).unwrap();
+        repo.find_commit(tag_id).unwrap();
         let mut revwalk = repo.revwalk().unwrap();
 
         revwalk.push(commit_id3).unwrap();
@@ -1,52 +1,70 @@
 use crate::err::CliError;
-use reqwest::{Client, Response};
-use serde::export::fmt::Error;
-use serde::export::Formatter;
-use serde::Deserialize;
-use std::fmt::Display;
-use std::collections::HashSet;
-use std::iter::FromIterator;
+use crate::forge::{ApiRequest, Forge, MergeRequest, User};
+use crate::render::MrLinks;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Method, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+/// Kept well under URL length limits, and no more than one page's worth.
+const IIDS_PER_REQUEST: usize = 100;
+const PER_PAGE: usize = 100;
 
 pub struct Project {
     base_url: String,
-    project_id: u64,
+    /// Either the numeric id or the url encoded `group/project` path, GitLab takes both.
+    project_id: String,
     api_token: String,
     client: reqwest::Client,
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
-pub struct MergeRequest {
-    iid: u64,
-    title: String,
-    description: String,
-    pub author: User,
+#[derive(Debug, Serialize)]
+struct ReleaseBody<'a> {
+    tag_name: &'a str,
+    name: &'a str,
+    description: &'a str,
 }
 
-impl Display for MergeRequest {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
-        f.write_str(self.title.as_str())
-            .and(f.write_str(" by "))
-            .and(f.write_str(self.author.name.as_str()))
-    }
+#[derive(Debug, Deserialize)]
+struct ProjectInfo {
+    web_url: String,
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
-pub struct User {
+#[derive(Debug, Deserialize)]
+struct GroupInfo {
     id: u64,
-    name: String,
-    pub username: String,
 }
 
 impl Project {
     pub fn new(base_url: String, project_id: u64, api_token: String) -> Project {
         Project {
             base_url,
-            project_id,
+            project_id: project_id.to_string(),
+            api_token,
+            client: Client::new(),
+        }
+    }
+
+    /// A project found by its `group/project` path rather than its id.
+    pub fn for_path(base_url: String, path: &str, api_token: String) -> Project {
+        Project {
+            base_url,
+            project_id: path.replace("/", "%2F"),
             api_token,
             client: Client::new(),
         }
     }
 
+    /// Another project on the same GitLab instance, found by its `group/project` path.
+    pub fn sibling(&self, path: &str) -> Project {
+        Project {
+            base_url: self.base_url.clone(),
+            project_id: path.replace("/", "%2F"),
+            api_token: self.api_token.clone(),
+            client: self.client.clone(),
+        }
+    }
+
     fn get(&self, url : String) -> Result<Response, CliError<'static>> {
         let req = self.client
             .get(url.as_str())
@@ -65,25 +83,25 @@ impl Project {
         })
     }
 
-    pub fn get_mrs(&self, mr_ids: Vec<u64>) -> Result<Vec<MergeRequest>, CliError<'static>> {
-        if mr_ids.is_empty() {
-            return Ok(Vec::new())
-        }
+    /// Like [`Project::get`], but anything other than a success is an error.
+    fn get_ok(&self, url: String) -> Result<Response, CliError<'static>> {
+        let response = self.get(url.clone())?;
 
-        let mr_strs : Vec<String> = mr_ids.iter()
-            .map(|id| format!("iids[]={}", id))
-            .collect();
+        if !response.status().is_success() {
+            return Err(CliError::String(format!(
+                "Got {} from {}",
+                response.status(),
+                url
+            )));
+        }
 
-        let mut url = format!(
-            "{base_url}/api/v4/projects/{project_id}/merge_requests?{mrs}",
-            base_url = self.base_url,
-            project_id = self.project_id,
-            mrs = mr_strs.join("&")
-        );
+        Ok(response)
+    }
 
-        let mut response = self.get(url.clone())?;
+    fn get_json<T: DeserializeOwned>(&self, url: String) -> Result<T, CliError<'static>> {
+        let mut response = self.get_ok(url.clone())?;
 
-        response.json::<Vec<MergeRequest>>().map_err(|e| {
+        response.json::<T>().map_err(|e| {
             let message = format!(
                 "Could not deserialize json from {}:\n {:#?}",
                 url, response
@@ -92,6 +110,123 @@ impl Project {
         })
     }
 
+    /// The project's page, e.g. `https://gitlab.com/group/project`.
+    pub fn get_web_url(&self) -> Result<String, CliError<'static>> {
+        let url = format!(
+            "{base_url}/api/v4/projects/{project_id}",
+            base_url = self.base_url,
+            project_id = self.project_id
+        );
+
+        self.get_json::<ProjectInfo>(url).map(|info| info.web_url)
+    }
+
+    pub fn get_mrs(&self, mr_ids: Vec<u64>) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        let mut mrs = Vec::new();
+
+        for batch in mr_ids.chunks(IIDS_PER_REQUEST) {
+            let mr_strs : Vec<String> = batch.iter()
+                .map(|id| format!("iids[]={}", id))
+                .collect();
+
+            let url = format!(
+                "{base_url}/api/v4/projects/{project_id}/merge_requests?per_page={per_page}&{mrs}",
+                base_url = self.base_url,
+                project_id = self.project_id,
+                per_page = PER_PAGE,
+                mrs = mr_strs.join("&")
+            );
+
+            mrs.extend(self.get_pages::<MergeRequest>(url)?);
+        }
+
+        Ok(mrs)
+    }
+
+    /// Usernames of `group`'s direct members, and of its subgroups' all the way down with
+    /// `subgroups`. Members inherited from parent groups are left out.
+    pub fn get_group_members(
+        &self,
+        group: &str,
+        subgroups: bool,
+    ) -> Result<Vec<String>, CliError<'static>> {
+        let mut group_ids = vec![group.replace("/", "%2F")];
+        if subgroups {
+            let url = format!(
+                "{base_url}/api/v4/groups/{group}/descendant_groups?per_page={per_page}",
+                base_url = self.base_url,
+                group = group_ids[0],
+                per_page = PER_PAGE
+            );
+            let descendants = self.get_pages::<GroupInfo>(url)?;
+            group_ids.extend(descendants.iter().map(|g| g.id.to_string()));
+        }
+
+        let mut usernames = Vec::new();
+        for group_id in group_ids {
+            let url = format!(
+                "{base_url}/api/v4/groups/{group}/members?per_page={per_page}",
+                base_url = self.base_url,
+                group = group_id,
+                per_page = PER_PAGE
+            );
+            for member in self.get_pages::<User>(url)? {
+                if !usernames.contains(&member.username) {
+                    usernames.push(member.username);
+                }
+            }
+        }
+
+        Ok(usernames)
+    }
+
+    /// Everything from `url` and the pages after it.
+    fn get_pages<T: DeserializeOwned>(&self, url: String) -> Result<Vec<T>, CliError<'static>> {
+        let mut items = Vec::new();
+        let mut url = Some(url);
+
+        while let Some(page_url) = url {
+            let mut response = self.get_ok(page_url.clone())?;
+            url = next_page_url(page_url.as_str(), &response);
+
+            let page = response.json::<Vec<T>>().map_err(|e| {
+                let message = format!(
+                    "Could not deserialize json from {}:\n {:#?}",
+                    page_url, response
+                );
+                CliError::Http(message, e)
+            })?;
+            items.extend(page);
+        }
+
+        Ok(items)
+    }
+
+    /// The merge requests GitLab knows contain `sha`, which works for squash and fast-forward
+    /// merges that leave no trailer in the commit message. None for a commit GitLab doesn't
+    /// have, e.g. one that hasn't been pushed.
+    pub fn get_mrs_for_commit(&self, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        let url = format!(
+            "{base_url}/api/v4/projects/{project_id}/repository/commits/{sha}/merge_requests",
+            base_url = self.base_url,
+            project_id = self.project_id,
+            sha = sha
+        );
+
+        let mut response = self.get(url.clone())?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(Vec::new()),
+            status if status.is_success() => response.json::<Vec<MergeRequest>>().map_err(|e| {
+                let message = format!(
+                    "Could not deserialize json from {}:\n {:#?}",
+                    url, response
+                );
+                CliError::Http(message, e)
+            }),
+            status => Err(CliError::String(format!("Got {} from {}", status, url))),
+        }
+    }
+
     pub fn get_mr(&self, mr_id: u64) -> Result<MergeRequest, CliError<'static>> {
         let url = format!(
             "{base_url}/api/v4/projects/{project_id}/merge_requests/{mr_id}",
@@ -100,21 +235,442 @@ impl Project {
             mr_id = mr_id
         );
 
-        let mut response = self.get(url.clone())?;
+        self.get_json::<MergeRequest>(url)
+    }
+}
 
-        response.json::<MergeRequest>().map_err(|e| {
-            let message = format!(
-                "Could not deserialize json from {}:\n {:#?}",
-                url, response
-            );
-            CliError::Http(message, e)
+impl Forge for Project {
+    fn get_mrs(
+        &self,
+        project: Option<&str>,
+        ids: Vec<u64>,
+    ) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        match project {
+            None => Project::get_mrs(self, ids),
+            Some(path) => Ok(self
+                .sibling(path)
+                .get_mrs(ids)?
+                .into_iter()
+                .map(|mut mr| {
+                    mr.project = Some(path.to_string());
+                    mr
+                })
+                .collect()),
+        }
+    }
+
+    fn get_mrs_for_commit(&self, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        Project::get_mrs_for_commit(self, sha)
+    }
+
+    fn group_members(
+        &self,
+        group: &str,
+        subgroups: bool,
+    ) -> Result<Vec<String>, CliError<'static>> {
+        self.get_group_members(group, subgroups)
+    }
+
+    fn links(&self) -> Result<MrLinks, CliError<'static>> {
+        Ok(MrLinks::new(
+            self.base_url.as_str(),
+            self.get_web_url()?.as_str(),
+        ))
+    }
+
+    fn release_request(
+        &self,
+        tag: &str,
+        description: &str,
+    ) -> Result<ApiRequest, CliError<'static>> {
+        let releases_url = format!(
+            "{base_url}/api/v4/projects/{project_id}/releases",
+            base_url = self.base_url,
+            project_id = self.project_id
+        );
+        let release_url = format!("{}/{}", releases_url, tag.replace("/", "%2F"));
+
+        let response = self.get(release_url.clone())?;
+        let (method, url) = match response.status() {
+            StatusCode::NOT_FOUND => (Method::POST, releases_url),
+            status if status.is_success() => (Method::PUT, release_url),
+            status => {
+                return Err(CliError::String(format!(
+                    "Could not check for release {}, got {} from {}",
+                    tag, status, release_url
+                )))
+            }
+        };
+
+        let body = serde_json::to_string_pretty(&ReleaseBody {
+            tag_name: tag,
+            name: tag,
+            description,
+        })
+        .map_err(|e| CliError::Json("Could not serialize release", e))?;
+
+        Ok(ApiRequest { method, url, body })
+    }
+
+    fn send(&self, request: &ApiRequest) -> Result<(), CliError<'static>> {
+        let mut response = self
+            .client
+            .request(request.method.clone(), request.url.as_str())
+            .header("Private-Token", self.api_token.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.body.clone())
+            .send()
+            .map_err(|e| {
+                CliError::Http(format!("Error sending {} {}", request.method, request.url), e)
+            })?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(CliError::String(format!(
+                "{} {} failed with {}:\n{}",
+                request.method,
+                request.url,
+                response.status(),
+                response.text().unwrap_or_default()
+            )))
+        }
+    }
+}
+
+/// Prefers the `Link` header's `rel="next"` url, falling back to `X-Next-Page`.
+fn next_page_url(url: &str, response: &Response) -> Option<String> {
+    let headers = response.headers();
+
+    let from_link = headers
+        .get("Link")
+        .and_then(|h| h.to_str().ok())
+        .and_then(next_link);
+    if from_link.is_some() {
+        return from_link;
+    }
+
+    headers
+        .get("X-Next-Page")
+        .and_then(|h| h.to_str().ok())
+        .map(str::trim)
+        .filter(|page| !page.is_empty())
+        .map(|page| with_page(url, page))
+}
+
+fn next_link(link: &str) -> Option<String> {
+    let next = link.split(',').find(|l| l.contains("rel=\"next\""))?;
+    let start = next.find('<')? + 1;
+    let end = next.find('>')?;
+
+    Some(next[start..end].to_string())
+}
+
+fn with_page(url: &str, page: &str) -> String {
+    let (path, query) = url.split_once('?').unwrap_or((url, ""));
+    let mut params: Vec<&str> = query
+        .split('&')
+        .filter(|p| !p.is_empty() && !p.starts_with("page="))
+        .collect();
+    let page_param = format!("page={}", page);
+    params.push(page_param.as_str());
+
+    format!("{}?{}", path, params.join("&"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forge::{ApiRequest, Forge, MergeRequest};
+    use crate::git_lab::{next_link, with_page, Project};
+    use crate::mr_helpers::mr_helpers::{json_list, mr, MrBuilder};
+    use reqwest::Method;
+    use crate::http_helpers::http_helpers::{mock_server, MockRequest, MockResponse};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn can_find_next_link() {
+        let link = r#"<https://gitlab.com/api/v4/x?page=1>; rel="prev", <https://gitlab.com/api/v4/x?page=3>; rel="next""#;
+
+        assert_eq!(
+            next_link(link),
+            Some("https://gitlab.com/api/v4/x?page=3".to_string())
+        );
+        assert_eq!(
+            next_link(r#"<https://gitlab.com/api/v4/x?page=1>; rel="first""#),
+            None
+        );
+    }
+
+    #[test]
+    fn with_page_replaces_existing_page() {
+        assert_eq!(
+            with_page("http://a/mrs?per_page=100&page=2", "3"),
+            "http://a/mrs?per_page=100&page=3"
+        );
+        assert_eq!(
+            with_page("http://a/mrs?iids[]=1", "2"),
+            "http://a/mrs?iids[]=1&page=2"
+        );
+    }
+
+    #[test]
+    fn get_mrs_batches_and_follows_pages() {
+        let largest_batch = Arc::new(Mutex::new(0));
+        let seen_batch = largest_batch.clone();
+
+        // Serves pages of 20 like GitLab's default, whatever per_page asks for.
+        let base_url = mock_server(move |req: &MockRequest| {
+            let iids = query_values(req, "iids[]");
+            let page: usize = query_values(req, "page")
+                .first()
+                .map(|p| p.parse().unwrap())
+                .unwrap_or(1);
+            {
+                let mut largest = seen_batch.lock().unwrap();
+                *largest = (*largest).max(iids.len());
+            }
+
+            let on_page: Vec<MrBuilder> = iids
+                .iter()
+                .skip((page - 1) * 20)
+                .take(20)
+                .map(|iid| mr(iid.parse().unwrap()))
+                .collect();
+            let response = MockResponse::json(&json_list(on_page));
+            if page * 20 < iids.len() {
+                response.with_header("X-Next-Page", &(page + 1).to_string())
+            } else {
+                response.with_header("X-Next-Page", "")
+            }
+        });
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        let mr_ids: Vec<u64> = (1..=250).collect();
+        let mrs = proj.get_mrs(mr_ids.clone()).unwrap();
+
+        let iids: Vec<u64> = mrs.iter().map(|mr| mr.iid).collect();
+        assert_eq!(iids, mr_ids);
+        assert_eq!(*largest_batch.lock().unwrap(), 100);
+    }
+
+    #[test]
+    fn get_mrs_follows_link_header() {
+        let base_url = Arc::new(Mutex::new(String::new()));
+        let server_url = base_url.clone();
+
+        let url = mock_server(move |req: &MockRequest| {
+            if req.path.contains("cursor=next") {
+                MockResponse::json(&json_list(vec![mr(2)]))
+            } else {
+                let next = format!(
+                    "<{}/api/v4/projects/1/merge_requests?cursor=next>; rel=\"next\"",
+                    server_url.lock().unwrap()
+                );
+                MockResponse::json(&json_list(vec![mr(1)])).with_header("Link", &next)
+            }
+        });
+        *base_url.lock().unwrap() = url.clone();
+        let proj = Project::new(url, 1, "token".to_string());
+
+        let iids: Vec<u64> = proj
+            .get_mrs(vec![1, 2])
+            .unwrap()
+            .iter()
+            .map(|mr| mr.iid)
+            .collect();
+        assert_eq!(iids, vec![1, 2]);
+    }
+
+    #[test]
+    fn mrs_have_milestone_target_branch_and_draft() {
+        let json = mr(1).milestone("1.0").target_branch("main").json();
+        let mr: MergeRequest = serde_json::from_str(json.as_str()).unwrap();
+
+        assert_eq!(mr.milestone.unwrap().title, "1.0");
+        assert_eq!(mr.target_branch.as_deref(), Some("main"));
+        assert!(!mr.draft);
+    }
+
+    #[test]
+    fn get_mrs_reports_error_statuses() {
+        let base_url = mock_server(|_: &MockRequest| MockResponse::status(401));
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        let err = proj.get_mrs(vec![1]).unwrap_err();
+
+        assert!(err.to_string().starts_with("Got 401 Unauthorized from "));
+    }
+
+    #[test]
+    fn get_mrs_for_commit_is_empty_for_unknown_commits() {
+        let base_url = mock_server(|req: &MockRequest| match req.path.as_str() {
+            "/api/v4/projects/1/repository/commits/abc123/merge_requests" => {
+                MockResponse::json(&json_list(vec![mr(4)]))
+            }
+            "/api/v4/projects/1/repository/commits/def456/merge_requests" => {
+                MockResponse::status(500)
+            }
+            _ => MockResponse::status(404),
+        });
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        let found = proj.get_mrs_for_commit("abc123").unwrap();
+        assert_eq!(found.iter().map(|mr| mr.iid).collect::<Vec<_>>(), vec![4]);
+        assert!(proj.get_mrs_for_commit("fff000").unwrap().is_empty());
+        assert!(proj.get_mrs_for_commit("def456").is_err());
+    }
+
+    #[test]
+    fn get_mrs_skips_requests_for_no_ids() {
+        let proj = Project::new("http://localhost:1".to_string(), 1, "token".to_string());
+
+        assert_eq!(proj.get_mrs(vec![]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn can_get_web_url() {
+        let base_url = mock_server(|req: &MockRequest| {
+            assert_eq!(req.path, "/api/v4/projects/1");
+            MockResponse::json(r#"{"id": 1, "web_url": "https://gitlab.example.com/group/project"}"#)
+        });
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        assert_eq!(
+            proj.get_web_url().unwrap(),
+            "https://gitlab.example.com/group/project"
+        );
+    }
+
+    #[test]
+    fn group_members_include_subgroups_on_request() {
+        let base_url = mock_server(|req: &MockRequest| match req.path.as_str() {
+            "/api/v4/groups/platform%2Fbackend/members?per_page=100" => {
+                MockResponse::json(r#"[{"id": 1, "name": "Alice", "username": "alice"}]"#)
+                    .with_header("X-Next-Page", "2")
+            }
+            "/api/v4/groups/platform%2Fbackend/members?per_page=100&page=2" => {
+                MockResponse::json(r#"[{"id": 2, "name": "Bob", "username": "bob"}]"#)
+            }
+            "/api/v4/groups/platform%2Fbackend/descendant_groups?per_page=100" => {
+                MockResponse::json(r#"[{"id": 7, "full_path": "platform/backend/db"}]"#)
+            }
+            "/api/v4/groups/7/members?per_page=100" => MockResponse::json(
+                r#"[{"id": 2, "name": "Bob", "username": "bob"},
+                    {"id": 3, "name": "Carol", "username": "carol"}]"#,
+            ),
+            path => panic!("unexpected request for {}", path),
+        });
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        assert_eq!(
+            proj.get_group_members("platform/backend", false).unwrap(),
+            vec!["alice", "bob"]
+        );
+        assert_eq!(
+            proj.get_group_members("platform/backend", true).unwrap(),
+            vec!["alice", "bob", "carol"]
+        );
+    }
+
+    #[test]
+    fn release_request_creates_missing_release() {
+        let base_url = mock_server(|req: &MockRequest| {
+            assert_eq!(req.path, "/api/v4/projects/1/releases/release%2F2024.03.1");
+            MockResponse::status(404)
+        });
+        let proj = Project::new(base_url.clone(), 1, "token".to_string());
+
+        let request = proj.release_request("release/2024.03.1", "Notes").unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(request.url, format!("{}/api/v4/projects/1/releases", base_url));
+        let body: serde_json::Value = serde_json::from_str(request.body.as_str()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "tag_name": "release/2024.03.1",
+                "name": "release/2024.03.1",
+                "description": "Notes"
+            })
+        );
+    }
+
+    #[test]
+    fn release_request_updates_existing_release() {
+        let base_url = mock_server(|_: &MockRequest| {
+            MockResponse::json(r#"{"tag_name": "v1.2.0", "description": "Old notes"}"#)
+        });
+        let proj = Project::new(base_url.clone(), 1, "token".to_string());
+
+        let request = proj.release_request("v1.2.0", "Notes").unwrap();
+
+        assert_eq!(request.method, Method::PUT);
+        assert_eq!(
+            request.url,
+            format!("{}/api/v4/projects/1/releases/v1.2.0", base_url)
+        );
+    }
+
+    #[test]
+    fn send_makes_the_request() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let seen = received.clone();
+        let base_url = mock_server(move |req: &MockRequest| {
+            seen.lock()
+                .unwrap()
+                .push(format!("{} {} {}", req.method, req.path, req.body));
+            MockResponse::status(201)
+        });
+        let proj = Project::new(base_url.clone(), 1, "token".to_string());
+
+        proj.send(&ApiRequest {
+            method: Method::POST,
+            url: format!("{}/api/v4/projects/1/releases", base_url),
+            body: r#"{"tag_name": "v1.2.0"}"#.to_string(),
         })
+        .unwrap();
+
+        assert_eq!(
+            *received.lock().unwrap(),
+            vec![r#"POST /api/v4/projects/1/releases {"tag_name": "v1.2.0"}"#]
+        );
+    }
+
+    #[test]
+    fn send_reports_failures() {
+        let base_url = mock_server(|_: &MockRequest| MockResponse {
+            status: 403,
+            headers: Vec::new(),
+            body: r#"{"message": "403 Forbidden"}"#.to_string(),
+        });
+        let proj = Project::new(base_url.clone(), 1, "token".to_string());
+
+        let err = proj
+            .send(&ApiRequest {
+                method: Method::PUT,
+                url: format!("{}/api/v4/projects/1/releases/v1.2.0", base_url),
+                body: "{}".to_string(),
+            })
+            .unwrap_err();
+
+        assert!(err.to_string().contains("403 Forbidden"));
+    }
+
+    fn query_values(req: &MockRequest, name: &str) -> Vec<String> {
+        let query = req.path.split_once('?').map(|(_, q)| q).unwrap_or("");
+        let prefix = format!("{}=", name);
+        query
+            .split('&')
+            .map(|p| p.replace("%5B%5D", "[]"))
+            .filter_map(|p| p.strip_prefix(prefix.as_str()).map(str::to_string))
+            .collect()
     }
 }
 
 #[cfg(all(test, feature = "gitlab_api_tests"))]
 mod gitlab_api_tests {
-    use crate::git_lab::{Project, MergeRequest};
+    use crate::forge::MergeRequest;
+    use crate::git_lab::Project;
     use std::env;
     use crate::err::CliError;
 
@@ -133,7 +689,7 @@ mod gitlab_api_tests {
     #[test]
     fn can_get_mr() {
         let mr = PROJECT.get_mr(1).unwrap();
-        assert_eq!(mr.author.username, "lfn3")
+        assert_eq!(mr.author.unwrap().username, "lfn3")
     }
 
     #[test]
@@ -0,0 +1,63 @@
+use crate::err::CliError;
+use crate::forge::MergeRequest;
+use crate::rest::{self, Api, PullRequest};
+use reqwest::StatusCode;
+
+/// The merge commit Gitea writes, e.g. `Merge pull request 'Add export' (#12) from branch into main`.
+pub const MERGE_PATTERN: &str =
+    r"\AMerge pull request '.*' \((?P<project>[\w.-]+/[\w.-]+)?#(?P<iid>\d+)\)";
+/// The trailer Gitea adds to merge commits, e.g. `Reviewed-on: https://host/owner/repo/pulls/12`.
+pub const REVIEWED_ON_PATTERN: &str = r"(?m)^Reviewed-on: \S+/pulls/(?P<iid>\d+)$";
+/// The suffix Gitea adds to squashed commit titles, e.g. `Add export (#12)`.
+pub const SQUASH_PATTERN: &str = r"(?m)\A.*\((?P<project>[\w.-]+/[\w.-]+)?#(?P<iid>\d+)\)$";
+
+/// Gitea's api, a copy of GitHub's under `/api/v1`. Forgejo keeps it.
+pub struct Gitea;
+
+/// A repository on a Gitea or Forgejo instance, whose `base_url` is the instance's root, e.g.
+/// `https://codeberg.org`.
+pub type Repo = rest::Repo<Gitea>;
+
+impl Api for Gitea {
+    const NAME: &'static str = "Gitea";
+    const API_PATH: &'static str = "/api/v1";
+    const CHANGE_PATH: &'static str = "pulls";
+
+    /// Gitea only knows the one pull request that merged `sha`.
+    fn get_mrs_for_commit(repo: &Repo, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        let url = format!("{}/commits/{}/pull", repo.own_url(), sha);
+
+        let mut response = repo.get(url.as_str())?;
+        match response.status() {
+            StatusCode::NOT_FOUND => Ok(Vec::new()),
+            status if status.is_success() => response
+                .json::<PullRequest>()
+                .map(|pr| vec![MergeRequest::from(pr)])
+                .map_err(|e| CliError::Http(format!("Could not deserialize json from {}", url), e)),
+            status => Err(CliError::String(format!("Got {} from {}", status, url))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forge::Forge;
+    use crate::gitea::Repo;
+    use crate::http_helpers::http_helpers::{mock_server, MockRequest, MockResponse};
+
+    #[test]
+    fn get_mrs_for_commit_finds_the_merging_pull() {
+        let base_url = mock_server(|req: &MockRequest| match req.path.as_str() {
+            "/api/v1/repos/owner/repo/commits/abc123/pull" => MockResponse::json(
+                r#"{"number": 4, "title": "PR 4", "body": "", "user": {"id": 7, "login": "alice", "full_name": "Alice"}, "base": {"ref": "main"}}"#,
+            ),
+            _ => MockResponse::status(404),
+        });
+        let repo = Repo::new(base_url, "owner/repo", "token".to_string()).unwrap();
+
+        let found = repo.get_mrs_for_commit("abc123").unwrap();
+        assert_eq!(found.iter().map(|mr| mr.iid).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(found[0].to_string(), "PR 4 by Alice");
+        assert!(repo.get_mrs_for_commit("def456").unwrap().is_empty());
+    }
+}
@@ -0,0 +1,80 @@
+use crate::err::CliError;
+use crate::forge::MergeRequest;
+use crate::rest::{self, Api, PullRequest};
+use reqwest::header::ACCEPT;
+use reqwest::{RequestBuilder, StatusCode};
+
+/// The merge commit GitHub writes, e.g. `Merge pull request #12 from owner/branch`.
+pub const MERGE_PATTERN: &str = r"\AMerge pull request (?P<project>[\w.-]+/[\w.-]+)?#(?P<iid>\d+)";
+/// The suffix GitHub adds to squashed and rebased commit titles, e.g. `Add export (#12)`.
+pub const SQUASH_PATTERN: &str = r"(?m)\A.*\((?P<project>[\w.-]+/[\w.-]+)?#(?P<iid>\d+)\)$";
+
+/// GitHub's api, `https://api.github.com`, or `https://host/api/v3` on GitHub Enterprise.
+pub struct GitHub;
+
+/// A repository on GitHub, or a GitHub Enterprise instance.
+pub type Repo = rest::Repo<GitHub>;
+
+impl Api for GitHub {
+    const NAME: &'static str = "GitHub";
+    const API_PATH: &'static str = "";
+    const CHANGE_PATH: &'static str = "pull";
+
+    fn headers(request: RequestBuilder) -> RequestBuilder {
+        request.header(ACCEPT, "application/vnd.github.v3+json")
+    }
+
+    /// GitHub answers 422 rather than 404 for some commits it doesn't have, e.g. unpushed ones.
+    fn get_mrs_for_commit(repo: &Repo, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        let url = format!("{}/commits/{}/pulls", repo.own_url(), sha);
+
+        let mut response = repo.get(url.as_str())?;
+        match response.status() {
+            StatusCode::NOT_FOUND | StatusCode::UNPROCESSABLE_ENTITY => Ok(Vec::new()),
+            status if status.is_success() => response
+                .json::<Vec<PullRequest>>()
+                .map(|prs| prs.into_iter().map(MergeRequest::from).collect())
+                .map_err(|e| CliError::Http(format!("Could not deserialize json from {}", url), e)),
+            status => Err(CliError::String(format!("Got {} from {}", status, url))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forge::Forge;
+    use crate::github::Repo;
+    use crate::http_helpers::http_helpers::{mock_server, MockRequest, MockResponse};
+
+    #[test]
+    fn get_mrs_for_commit_lists_associated_pulls() {
+        let base_url = mock_server(|req: &MockRequest| {
+            assert_eq!(req.path, "/repos/owner/repo/commits/abc123/pulls");
+            assert_eq!(req.header("Accept"), Some("application/vnd.github.v3+json"));
+            MockResponse::json(
+                r#"[{"number": 4, "title": "PR 4", "body": null, "user": {"id": 7, "login": "alice"}, "base": {"ref": "main"}}]"#,
+            )
+        });
+        let repo = Repo::new(base_url, "owner/repo", "token".to_string()).unwrap();
+
+        let mrs = repo.get_mrs_for_commit("abc123").unwrap();
+
+        assert_eq!(mrs.iter().map(|mr| mr.iid).collect::<Vec<_>>(), vec![4]);
+        // GitHub has no display names here, the login stands in.
+        assert_eq!(mrs[0].to_string(), "PR 4 by alice");
+    }
+
+    #[test]
+    fn get_mrs_for_commit_is_empty_for_unknown_commits() {
+        let base_url = mock_server(|req: &MockRequest| match req.path.as_str() {
+            "/repos/owner/repo/commits/abc123/pulls" => MockResponse::status(422),
+            "/repos/owner/repo/commits/def456/pulls" => MockResponse::status(404),
+            _ => MockResponse::status(500),
+        });
+        let repo = Repo::new(base_url, "owner/repo", "token".to_string()).unwrap();
+
+        assert!(repo.get_mrs_for_commit("abc123").unwrap().is_empty());
+        assert!(repo.get_mrs_for_commit("def456").unwrap().is_empty());
+        assert!(repo.get_mrs_for_commit("fff000").is_err());
+    }
+}
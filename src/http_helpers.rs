@@ -0,0 +1,122 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+pub mod http_helpers {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::thread;
+
+    pub struct MockRequest {
+        pub method: String,
+        pub path: String,
+        pub headers: Vec<(String, String)>,
+        pub body: String,
+    }
+
+    impl MockRequest {
+        pub fn header(&self, name: &str) -> Option<&str> {
+            self.headers
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        }
+    }
+
+    pub struct MockResponse {
+        pub status: u16,
+        pub headers: Vec<(String, String)>,
+        pub body: String,
+    }
+
+    impl MockResponse {
+        pub fn json(body: &str) -> MockResponse {
+            MockResponse {
+                status: 200,
+                headers: Vec::new(),
+                body: body.to_string(),
+            }
+        }
+
+        pub fn status(status: u16) -> MockResponse {
+            MockResponse {
+                status,
+                headers: Vec::new(),
+                body: String::new(),
+            }
+        }
+
+        pub fn with_header(mut self, name: &str, value: &str) -> MockResponse {
+            self.headers.push((name.to_string(), value.to_string()));
+            self
+        }
+    }
+
+    /// Serves `handler`'s responses on a random local port until the test exits. Returns the
+    /// base url to point a client at.
+    pub fn mock_server<F>(handler: F) -> String
+    where
+        F: Fn(&MockRequest) -> MockResponse + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(s) => s,
+                    Err(_) => break,
+                };
+                let request = read_request(&mut BufReader::new(stream.try_clone().unwrap()));
+                let response = handler(&request);
+
+                let mut head = format!(
+                    "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                    response.status,
+                    response.body.len()
+                );
+                for (name, value) in response.headers {
+                    head.push_str(format!("{}: {}\r\n", name, value).as_str());
+                }
+                head.push_str("\r\n");
+
+                stream.write_all(head.as_bytes()).unwrap();
+                stream.write_all(response.body.as_bytes()).unwrap();
+            }
+        });
+
+        format!("http://{}", addr)
+    }
+
+    fn read_request<R: BufRead>(reader: &mut R) -> MockRequest {
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut parts = request_line.split_whitespace();
+        let method = parts.next().unwrap_or("").to_string();
+        let path = parts.next().unwrap_or("").to_string();
+
+        let mut headers = Vec::new();
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line.trim().is_empty() {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap();
+                }
+                headers.push((name.to_string(), value.trim().to_string()));
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+
+        MockRequest {
+            method,
+            path,
+            headers,
+            body: String::from_utf8(body).unwrap(),
+        }
+    }
+}
@@ -1,86 +1,579 @@
+pub mod cache;
+pub mod config;
+pub mod conventional;
 pub mod err;
+pub mod filter;
+pub mod forge;
+mod forge_helpers;
 mod git;
 mod git_helpers;
 pub mod git_lab;
+pub mod gitea;
+pub mod github;
+mod http_helpers;
+mod mr_helpers;
+pub mod next_version;
+pub mod render;
+pub mod rest;
+pub mod sections;
+pub mod tags;
 use serde::Deserialize;
 
 #[macro_use]
 extern crate lazy_static;
 
+pub use crate::git::{create_tag, push_tag, MrRef, DEFAULT_MR_PATTERN};
+
+use crate::conventional::CommitNote;
 use crate::err::CliError;
+use crate::filter::{Filter, FilterConfig, FilteredOut};
+use crate::forge::{ChangeKind, Forge, ForgeKind, MergeRequest};
 use crate::git_lab::Project;
-use git2::{Commit, Repository};
-use std::collections::HashMap;
+use crate::sections::Section;
+use crate::tags::{Bump, TagPattern, TagScheme};
+use git2::{Commit, Oid, Repository};
+use regex::Regex;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::process::{self, Stdio};
 
 #[derive(Debug, PartialEq, Deserialize)]
 pub struct Config {
-    pub base_url: String,
-    pub project_id: u64,
+    /// Where the project is hosted, GitLab unless this says otherwise.
+    #[serde(default)]
+    pub forge: ForgeKind,
+    /// The api's root, e.g. `https://gitlab.com` or `https://api.github.com`. Worked out from
+    /// the git remote when it's left out, see [`Config::fill_from_remote`].
+    pub base_url: Option<String>,
+    /// Also worked out from the git remote when it's left out.
+    pub project_id: Option<ProjectId>,
+    #[serde(default)]
     pub api_token: ApiToken,
-    pub teams: HashMap<String, Vec<String>>,
+    /// For `--team` and `team:` filters, by name.
+    #[serde(default)]
+    pub teams: HashMap<String, Team>,
+    /// Which merge requests make it into the notes, on top of `--include` and `--exclude`.
+    #[serde(default)]
+    pub filter: FilterConfig,
+    /// Used when no tag prefix or pattern is given on the command line.
+    pub tag_pattern: Option<String>,
+    /// Regexes for merge request references in commit messages, tried in order. Each needs an
+    /// `iid` named group, and may have a `project` group for references to other projects.
+    /// Defaults to the references the forge writes into merge commits.
+    pub mr_patterns: Option<Vec<String>>,
+    /// Headings to group merge requests under by label, in the order they're rendered.
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    /// A handlebars template to render the notes with, used when no `--format` is given.
+    pub template: Option<PathBuf>,
+    /// Read commits without a merge request as conventional commits.
+    #[serde(default)]
+    pub conventional_commits: bool,
+    /// How long fetched merge requests are cached for, 0 turns the cache off.
+    #[serde(default = "default_cache_ttl_hours")]
+    pub cache_ttl_hours: u64,
+    /// How long teams' group members are cached for, kept short as people join and leave groups
+    /// far more often than merged merge requests change. 0 fetches them every run.
+    #[serde(default = "default_group_cache_ttl_hours")]
+    pub group_cache_ttl_hours: u64,
+}
+
+fn default_cache_ttl_hours() -> u64 {
+    7 * 24
+}
+
+fn default_group_cache_ttl_hours() -> u64 {
+    1
+}
+
+/// GitLab takes either, GitHub only has paths.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum ProjectId {
+    Id(u64),
+    /// `group/project` on GitLab, `owner/repo` on GitHub.
+    Path(String),
+}
+
+impl fmt::Display for ProjectId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProjectId::Id(id) => write!(f, "{}", id),
+            ProjectId::Path(path) => f.write_str(path),
+        }
+    }
+}
+
+/// What's used when there's no config file, with every field at its default.
+impl Default for Config {
+    fn default() -> Config {
+        serde_yaml::from_str("{}").expect("every field has a default")
+    }
+}
+
+impl Config {
+    /// Fills in `base_url` and `project_id` from where `remote` points, when they aren't set.
+    pub fn fill_from_remote(
+        &mut self,
+        repo: &Repository,
+        remote: &str,
+    ) -> Result<(), CliError<'static>> {
+        if self.base_url.is_some() && self.project_id.is_some() {
+            return Ok(());
+        }
+
+        let remote = git::remote_url(repo, remote)?;
+        if self.base_url.is_none() {
+            self.base_url = Some(
+                self.forge
+                    .api_url(remote.scheme.as_str(), remote.host.as_str()),
+            );
+        }
+        if self.project_id.is_none() {
+            self.project_id = Some(ProjectId::Path(remote.path));
+        }
+        Ok(())
+    }
+
+    pub fn forge(&self) -> Result<Box<dyn Forge>, CliError<'static>> {
+        let (base_url, project_id) = match (&self.base_url, &self.project_id) {
+            (Some(base_url), Some(project_id)) => (base_url.clone(), project_id),
+            _ => {
+                return Err(CliError::Str(
+                    "No base_url or project_id in the config, or from the git remote",
+                ))
+            }
+        };
+        let token = self.api_token.get(self.forge, base_url.as_str())?;
+
+        match (self.forge, project_id) {
+            (ForgeKind::GitLab, ProjectId::Id(id)) => {
+                Ok(Box::new(Project::new(base_url, *id, token)))
+            }
+            (ForgeKind::GitLab, ProjectId::Path(path)) => {
+                Ok(Box::new(Project::for_path(base_url, path, token)))
+            }
+            (ForgeKind::GitHub, ProjectId::Path(path)) => {
+                Ok(Box::new(github::Repo::new(base_url, path, token)?))
+            }
+            (ForgeKind::Gitea, ProjectId::Path(path)) => {
+                Ok(Box::new(gitea::Repo::new(base_url, path, token)?))
+            }
+            (ForgeKind::GitHub, ProjectId::Id(_)) | (ForgeKind::Gitea, ProjectId::Id(_)) => Err(
+                CliError::Str("GitHub and Gitea projects need an owner/repo project_id"),
+            ),
+        }
+    }
 }
 
+/// Whose merge requests `--team` keeps.
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum Team {
+    /// Usernames, e.g. `[alice, bob]`.
+    Members(Vec<String>),
+    /// A GitLab group's members, e.g. `{group: platform/backend}`, looked up each run or from the
+    /// cache. With `subgroups: true`, members of the groups under it count too.
+    Group {
+        group: String,
+        #[serde(default)]
+        subgroups: bool,
+    },
+}
+
+impl Team {
+    pub fn members(&self, forge: &dyn Forge) -> Result<Vec<String>, CliError<'static>> {
+        match self {
+            Team::Members(usernames) => Ok(usernames.clone()),
+            Team::Group { group, subgroups } => forge.group_members(group, *subgroups),
+        }
+    }
+}
+
+/// Defaults to [`ApiToken::GitCredential`], which needs no setup for anyone who can already
+/// push over https.
 #[derive(Debug, PartialEq, Deserialize)]
 #[serde(tag = "from")]
 pub enum ApiToken {
-    EnvVar { name: String },
+    EnvVar {
+        name: String,
+    },
+    /// e.g. a mounted secret. Surrounding whitespace is trimmed.
+    File {
+        path: PathBuf,
+    },
+    /// Run with `sh -c`, e.g. `pass show gitlab/token`. Surrounding whitespace is trimmed from
+    /// its output.
+    Command {
+        cmd: String,
+    },
+    /// The password `git credential fill` has for `host`, or for the forge's own host, e.g.
+    /// `github.com` for `https://api.github.com`.
+    GitCredential {
+        #[serde(default)]
+        host: Option<String>,
+    },
+}
+
+impl Default for ApiToken {
+    fn default() -> ApiToken {
+        ApiToken::GitCredential { host: None }
+    }
 }
 
 impl ApiToken {
-    pub fn get(&self) -> Result<String, CliError<'static>> {
-        match self {
+    pub fn get(&self, forge: ForgeKind, base_url: &str) -> Result<String, CliError<'static>> {
+        let token = match self {
             ApiToken::EnvVar { name } => env::var(name).map_err(|_err| {
                 CliError::String(format!(
                     "Could not find api token in environment variable {}",
                     name
                 ))
-            }),
+            })?,
+            ApiToken::File { path } => fs::read_to_string(path)
+                .map_err(|e| CliError::Io("Could not read api token file", e))?,
+            ApiToken::Command { cmd } => {
+                let output = process::Command::new("sh")
+                    .arg("-c")
+                    .arg(cmd)
+                    .stderr(Stdio::inherit())
+                    .output()
+                    .map_err(|e| CliError::Io("Could not run api token command", e))?;
+                if !output.status.success() {
+                    return Err(CliError::String(format!(
+                        "Api token command failed with {}: {}",
+                        output.status, cmd
+                    )));
+                }
+                String::from_utf8_lossy(&output.stdout).into_owned()
+            }
+            ApiToken::GitCredential { host } => {
+                let (protocol, url_host) = git::protocol_and_host(base_url)?;
+                let host = host.as_deref().unwrap_or_else(|| forge.web_host(url_host));
+                git::credential_fill(protocol, host)?
+            }
+        };
+
+        let token = token.trim();
+        if token.is_empty() {
+            Err(CliError::Str("The api token is empty"))
+        } else {
+            Ok(token.to_string())
         }
     }
 }
 
-pub fn print_release_notes(
-    proj: &Project,
+/// Where a release starts from.
+#[derive(Debug)]
+pub enum Since {
+    /// The greatest matching tag anywhere in the repo.
+    GreatestTag {
+        pattern: TagPattern,
+        scheme: TagScheme,
+    },
+    /// The closest matching tag that `up_to` can reach, like `git describe --tags --match`.
+    NearestTag {
+        pattern: TagPattern,
+        scheme: TagScheme,
+    },
+    /// An explicit ref, skipping tag discovery.
+    Ref(String),
+}
+
+impl Since {
+    pub fn resolve(&self, repo: &Repository, up_to: &str) -> Result<String, CliError<'static>> {
+        match self {
+            Since::GreatestTag { pattern, scheme } => git::find_greatest_tag(repo, pattern, scheme),
+            Since::NearestTag { pattern, scheme } => {
+                git::find_nearest_tag(repo, up_to, pattern, scheme)
+            }
+            Since::Ref(r) => Ok(r.clone()),
+        }
+    }
+
+    /// Where the notes for a release of `tag` start. Tags are looked for from `tag^` back, even
+    /// for [`Since::GreatestTag`], so an older release doesn't start from a newer tag and the
+    /// newest doesn't start from itself. The first release, with no tag before it, starts from the
+    /// root commit. An explicit ref has to come before `tag`.
+    pub fn before_release(&self, repo: &Repository, tag: &str) -> Result<Since, CliError<'static>> {
+        match self {
+            Since::GreatestTag { pattern, scheme } | Since::NearestTag { pattern, scheme } => {
+                let root = git::root_commit(repo, tag)?;
+                let nearest = if git::is_ancestor(repo, root.as_str(), tag)? {
+                    let parent = format!("{}^", tag);
+                    git::nearest_tag(repo, parent.as_str(), pattern, scheme)?
+                } else {
+                    None
+                };
+                Ok(Since::Ref(nearest.unwrap_or(root)))
+            }
+            Since::Ref(base) if git::is_ancestor(repo, base.as_str(), tag)? => {
+                Ok(Since::Ref(base.clone()))
+            }
+            Since::Ref(base) => Err(CliError::String(format!(
+                "{} doesn't come before {}, so the release notes can't start from it",
+                base, tag
+            ))),
+        }
+    }
+
+    /// The tag after `base`, which should be the tag this resolved to.
+    pub fn next_tag(&self, base: &str, bump: Bump) -> Result<String, CliError<'static>> {
+        let (pattern, scheme) = match self {
+            Since::GreatestTag { pattern, scheme } | Since::NearestTag { pattern, scheme } => {
+                (pattern, scheme)
+            }
+            Since::Ref(_) => {
+                return Err(CliError::Str(
+                    "Can't work out the next tag from --since, it needs a tag prefix or pattern",
+                ))
+            }
+        };
+
+        pattern
+            .version(base)
+            .and_then(|version| scheme.bump(version, bump))
+            .and_then(|next| pattern.with_version(base, next.as_str()))
+            .ok_or_else(|| {
+                CliError::String(format!("Could not work out the version after {}", base))
+            })
+    }
+}
+
+/// How commits are tied back to merge requests.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MrLookup {
+    /// Only read references out of commit messages.
+    Trailers,
+    /// Also ask GitLab about commits without a reference, for squash and fast-forward merges.
+    TrailersThenForge,
+}
+
+/// Ties commits back to the merge requests they came from.
+#[derive(Debug)]
+pub struct MrMatcher {
+    patterns: Vec<Regex>,
+    lookup: MrLookup,
+}
+
+impl MrMatcher {
+    pub fn new(patterns: &[String], lookup: MrLookup) -> Result<MrMatcher, CliError<'static>> {
+        let mut compiled = Vec::new();
+        for pattern in patterns {
+            let regex = Regex::new(pattern.as_str()).map_err(|e| {
+                CliError::Regex(format!("Could not parse mr pattern: {}", pattern), e)
+            })?;
+            if regex.capture_names().flatten().all(|name| name != "iid") {
+                return Err(CliError::String(format!(
+                    "MR pattern has no (?P<iid>...) group: {}",
+                    pattern
+                )));
+            }
+            compiled.push(regex);
+        }
+
+        Ok(MrMatcher {
+            patterns: compiled,
+            lookup,
+        })
+    }
+
+    /// The merge requests `commit` mentions, and the ones the forge has already fetched for it
+    /// when it mentions none. Those have to be merged, by a merge commit among `commits` when the
+    /// forge knows it, as the commit is also in open ones and ones into other branches.
+    fn find(
+        &self,
+        forge: Option<&dyn Forge>,
+        commit: &Commit,
+        commits: &[Oid],
+    ) -> Result<(Vec<MrRef>, Vec<MergeRequest>), CliError<'static>> {
+        let found = git::associated_mrs(commit, &self.patterns);
+        let forge = match forge {
+            Some(forge) if found.is_empty() && self.lookup == MrLookup::TrailersThenForge => forge,
+            _ => return Ok((found, Vec::new())),
+        };
+
+        let mrs: Vec<MergeRequest> = forge
+            .get_mrs_for_commit(commit.id().to_string().as_str())?
+            .into_iter()
+            .filter(|mr| mr.merged && merged_into(mr, commits))
+            .collect();
+        let found = mrs
+            .iter()
+            .map(|mr| MrRef {
+                project: mr.project.clone(),
+                iid: mr.iid,
+            })
+            .collect();
+
+        Ok((found, mrs))
+    }
+}
+
+/// Whether `mr`'s merge commit is one of `commits`, or it doesn't say which it is.
+fn merged_into(mr: &MergeRequest, commits: &[Oid]) -> bool {
+    let mut merge_commits = mr.merge_commits().peekable();
+    merge_commits.peek().is_none()
+        || merge_commits.any(|sha| matches!(Oid::from_str(sha), Ok(oid) if commits.contains(&oid)))
+}
+
+/// Where merge requests' titles and the rest come from.
+#[derive(Clone, Copy)]
+pub enum MrSource<'a> {
+    Forge(&'a dyn Forge),
+    /// Only what the commits that mention them say, for `--offline`. There are no authors or
+    /// labels, and [`MrLookup::TrailersThenForge`] only reads trailers.
+    Commits(ChangeKind),
+}
+
+/// What happens to commits that can't be tied to a merge request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnmatchedCommits {
+    /// Leave them in [`ReleaseNotes::unmatched_commits`].
+    List,
+    /// Put the ones that are conventional commits in the notes alongside the merge requests.
+    Conventional,
+}
+
+/// Everything shippy found while working out the notes for a release.
+#[derive(Debug)]
+pub struct ReleaseNotes {
+    /// The tag or ref the release is measured from (exclusive).
+    pub base: String,
+    /// The ref the release is measured up to (inclusive).
+    pub up_to: String,
+    /// Commits between `base` and `up_to`, newest first.
+    pub commits: Vec<Oid>,
+    /// Merge requests referenced by `commits`.
+    pub mr_ids: Vec<MrRef>,
+    /// Commits that couldn't be tied to any merge request, or read as a conventional commit.
+    pub unmatched_commits: Vec<Oid>,
+    /// Commits without a merge request that are in the notes in their own right.
+    pub commit_notes: Vec<CommitNote>,
+    /// Merge requests that made it through the filters.
+    pub mrs: Vec<MergeRequest>,
+    /// Merge requests that were fetched but removed by the filters.
+    pub filtered_out: Vec<FilteredOut>,
+}
+
+pub fn build_release_notes(
+    source: MrSource,
     repo: &Repository,
-    tag_prefix: &str,
+    since: &Since,
     up_to: &str,
-    for_authors: Option<&Vec<String>>
-) -> Result<(), CliError<'static>> {
-    let max_tag = git::find_greatest_tag(repo, tag_prefix)?;
-    println!("Searching between {} and {}", max_tag, up_to);
-
-    let commits = git::commits_between_refs(repo, up_to, max_tag.as_str())?;
-    print!("Found {} commits", commits.len());
-
-    let mr_ids: Vec<u64> = commits
-        .into_iter()
-        .map(|c: Commit| git::associated_mr(&c))
-        .filter(|o| o.is_some())
-        .map(|o| o.unwrap())
-        .collect();
-    println!(", pointing to {} merge requests:", mr_ids.len());
+    mr_matcher: &MrMatcher,
+    unmatched: UnmatchedCommits,
+    filter: &Filter,
+) -> Result<ReleaseNotes, CliError<'static>> {
+    let base = since.resolve(repo, up_to)?;
 
-    let mrs = proj.get_mrs(mr_ids)?;
+    let commits: Vec<Oid> = git::commits_between_refs(repo, up_to, base.as_str())?
+        .iter()
+        .map(Commit::id)
+        .collect();
 
-    let filtered_mrs = if let Some(authors) = for_authors {
-        println!("Filtering to {:?}", authors);
-        mrs.into_iter().filter(|mr| authors.contains(&mr.author.username.to_string())).collect()
-    } else {
-        mrs
+    let forge = match source {
+        MrSource::Forge(forge) => Some(forge),
+        MrSource::Commits(_) => None,
     };
 
-    for mr in filtered_mrs {
-        println!("{}", mr)
+    let mut mr_ids: Vec<MrRef> = Vec::new();
+    let mut mrs = Vec::new();
+    // Looked up by commit already, so not fetched again.
+    let mut fetched: Vec<MrRef> = Vec::new();
+    let mut unmatched_commits = Vec::new();
+    let mut commit_notes = Vec::new();
+    for oid in &commits {
+        let commit = repo
+            .find_commit(*oid)
+            .map_err(|e| CliError::Git("Could not find commit", e))?;
+
+        let (found, found_mrs) = mr_matcher.find(forge, &commit, &commits)?;
+        for mr in found_mrs {
+            let mr_ref = MrRef {
+                project: mr.project.clone(),
+                iid: mr.iid,
+            };
+            if !fetched.contains(&mr_ref) {
+                fetched.push(mr_ref);
+                mrs.push(mr);
+            }
+        }
+
+        if found.is_empty() {
+            let conventional = match unmatched {
+                UnmatchedCommits::List => None,
+                UnmatchedCommits::Conventional => commit.message().and_then(conventional::parse),
+            };
+            match conventional {
+                Some(parsed) => commit_notes.push(CommitNote {
+                    id: *oid,
+                    commit: parsed,
+                }),
+                None => unmatched_commits.push(*oid),
+            }
+        }
+        for mr_ref in found {
+            if !mr_ids.contains(&mr_ref) {
+                if let MrSource::Commits(kind) = source {
+                    let message = commit.message().unwrap_or("");
+                    mrs.push(git::mr_from_commit(&mr_ref, message, kind));
+                }
+                mr_ids.push(mr_ref);
+            }
+        }
+    }
+
+    if let Some(forge) = forge {
+        let mut by_project: BTreeMap<Option<&str>, Vec<u64>> = BTreeMap::new();
+        for mr_ref in mr_ids.iter().filter(|mr_ref| !fetched.contains(mr_ref)) {
+            by_project
+                .entry(mr_ref.project.as_deref())
+                .or_default()
+                .push(mr_ref.iid);
+        }
+
+        for (project, iids) in by_project {
+            mrs.extend(forge.get_mrs(project, iids)?);
+        }
     }
 
-    Ok(())
+    let (mrs, filtered_out) = filter.apply(mrs);
+
+    Ok(ReleaseNotes {
+        base,
+        up_to: up_to.to_string(),
+        commits,
+        mr_ids,
+        unmatched_commits,
+        commit_notes,
+        mrs,
+        filtered_out,
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::filter::{Filter, FilterConfig};
+    use crate::forge::{ChangeKind, ForgeKind};
+    use crate::forge_helpers::forge_helpers::fake_forge;
+    use crate::git_helpers::git_helpers::{
+        commit_with_message, empty_commit, initial_commit, lightweight_tag, tmp_dir, tmp_repo,
+    };
+    use crate::git_lab::Project;
+    use crate::http_helpers::http_helpers::{mock_server, MockResponse};
+    use crate::mr_helpers::mr_helpers::{json_list, mr};
+    use crate::sections::Section;
+    use crate::tags::{Bump, TagPattern, TagScheme};
     use crate::ApiToken::EnvVar;
-    use crate::Config;
+    use crate::{
+        build_release_notes, ApiToken, Config, MrLookup, MrMatcher, MrRef, MrSource, ProjectId,
+        Since, Team, UnmatchedCommits, DEFAULT_MR_PATTERN,
+    };
+    use std::fs;
 
     #[test]
     fn can_deserialize_config_yaml() {
@@ -95,7 +588,16 @@ mod tests {
                     - Alice
         "#;
         let cfg: Config = serde_yaml::from_str(yaml_str).unwrap();
-        assert_eq!(cfg.project_id, 1234);
+        assert_eq!(cfg.forge, ForgeKind::GitLab);
+        assert_eq!(cfg.project_id, Some(ProjectId::Id(1234)));
+        assert_eq!(cfg.tag_pattern, None);
+        assert_eq!(cfg.mr_patterns, None);
+        assert_eq!(cfg.sections, vec![]);
+        assert_eq!(cfg.template, None);
+        assert!(!cfg.conventional_commits);
+        assert_eq!(cfg.cache_ttl_hours, 168);
+        assert_eq!(cfg.group_cache_ttl_hours, 1);
+        assert_eq!(cfg.filter, FilterConfig::default());
         assert_eq!(
             cfg.api_token,
             EnvVar {
@@ -103,4 +605,607 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn teams_can_be_members_or_groups() {
+        let yaml_str = r#"
+            teams:
+                A: [alice, bob]
+                B:
+                    group: platform/backend
+                C:
+                    group: platform
+                    subgroups: true
+        "#;
+        let cfg: Config = serde_yaml::from_str(yaml_str).unwrap();
+
+        assert_eq!(
+            cfg.teams["A"],
+            Team::Members(vec!["alice".to_string(), "bob".to_string()])
+        );
+        assert_eq!(
+            cfg.teams["B"],
+            Team::Group {
+                group: "platform/backend".to_string(),
+                subgroups: false
+            }
+        );
+        assert_eq!(
+            cfg.teams["C"],
+            Team::Group {
+                group: "platform".to_string(),
+                subgroups: true
+            }
+        );
+    }
+
+    #[test]
+    fn config_has_defaults_for_everything() {
+        let cfg = Config::default();
+
+        assert_eq!(cfg.base_url, None);
+        assert_eq!(cfg.project_id, None);
+        assert_eq!(cfg.api_token, ApiToken::GitCredential { host: None });
+        assert!(cfg.teams.is_empty());
+    }
+
+    #[test]
+    fn config_fills_project_from_remote() {
+        let repo = tmp_repo();
+        repo.remote("origin", "git@gitlab.example.com:group/sub/project.git")
+            .unwrap();
+        repo.remote("github", "https://github.com/owner/repo.git")
+            .unwrap();
+        repo.remote("gitea", "http://git.internal:3000/owner/repo.git")
+            .unwrap();
+
+        let mut cfg = Config::default();
+        cfg.fill_from_remote(&repo, "origin").unwrap();
+        assert_eq!(cfg.base_url, Some("https://gitlab.example.com".to_string()));
+        assert_eq!(
+            cfg.project_id,
+            Some(ProjectId::Path("group/sub/project".to_string()))
+        );
+
+        let mut cfg = Config {
+            forge: ForgeKind::GitHub,
+            ..Config::default()
+        };
+        cfg.fill_from_remote(&repo, "github").unwrap();
+        assert_eq!(cfg.base_url, Some("https://api.github.com".to_string()));
+        assert_eq!(
+            cfg.project_id,
+            Some(ProjectId::Path("owner/repo".to_string()))
+        );
+
+        let mut cfg = Config {
+            forge: ForgeKind::Gitea,
+            ..Config::default()
+        };
+        cfg.fill_from_remote(&repo, "gitea").unwrap();
+        assert_eq!(cfg.base_url, Some("http://git.internal:3000".to_string()));
+    }
+
+    #[test]
+    fn config_keeps_what_it_has_over_remote() {
+        let repo = tmp_repo();
+        let mut cfg = Config {
+            base_url: Some("https://gitlab.internal".to_string()),
+            project_id: Some(ProjectId::Id(7)),
+            ..Config::default()
+        };
+
+        // There's no remote to read, so this would fail if it tried.
+        cfg.fill_from_remote(&repo, "origin").unwrap();
+
+        assert_eq!(cfg.base_url, Some("https://gitlab.internal".to_string()));
+        assert_eq!(cfg.project_id, Some(ProjectId::Id(7)));
+    }
+
+    #[test]
+    fn can_deserialize_github_config_yaml() {
+        let yaml_str = r#"
+            forge: GitHub
+            base_url: "https://api.github.com"
+            project_id: owner/repo
+            api_token:
+                from: EnvVar
+                name: API_TOKEN
+            teams: {}
+        "#;
+        let cfg: Config = serde_yaml::from_str(yaml_str).unwrap();
+        assert_eq!(cfg.forge, ForgeKind::GitHub);
+        assert_eq!(
+            cfg.project_id,
+            Some(ProjectId::Path("owner/repo".to_string()))
+        );
+    }
+
+    #[test]
+    fn can_deserialize_other_api_token_sources() {
+        let tokens: Vec<ApiToken> = serde_yaml::from_str(
+            r#"
+            - from: File
+              path: /var/run/secrets/gitlab-token
+            - from: Command
+              cmd: pass show gitlab/token
+            - from: GitCredential
+            - from: GitCredential
+              host: github.com
+        "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            tokens,
+            vec![
+                ApiToken::File {
+                    path: "/var/run/secrets/gitlab-token".into()
+                },
+                ApiToken::Command {
+                    cmd: "pass show gitlab/token".to_string()
+                },
+                ApiToken::GitCredential { host: None },
+                ApiToken::GitCredential {
+                    host: Some("github.com".to_string())
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn api_token_from_file_is_trimmed() {
+        let path = tmp_dir().join("token");
+        fs::write(&path, "s3cret\n").unwrap();
+
+        let token = ApiToken::File { path }
+            .get(ForgeKind::GitLab, "https://gitlab.com")
+            .unwrap();
+
+        assert_eq!(token, "s3cret");
+    }
+
+    #[test]
+    fn api_token_from_command_is_trimmed_stdout() {
+        let token = ApiToken::Command {
+            cmd: "printf '  s3cret\\n'".to_string(),
+        };
+        assert_eq!(
+            token.get(ForgeKind::GitLab, "https://gitlab.com").unwrap(),
+            "s3cret"
+        );
+
+        let failing = ApiToken::Command {
+            cmd: "exit 1".to_string(),
+        };
+        assert!(failing
+            .get(ForgeKind::GitLab, "https://gitlab.com")
+            .is_err());
+
+        let empty = ApiToken::Command {
+            cmd: "true".to_string(),
+        };
+        assert!(empty.get(ForgeKind::GitLab, "https://gitlab.com").is_err());
+    }
+
+    #[test]
+    fn can_deserialize_tag_pattern_from_config_yaml() {
+        let yaml_str = r#"
+            base_url: "https://gitlab.com"
+            project_id: 1234
+            api_token:
+                from: EnvVar
+                name: API_TOKEN
+            teams: {}
+            tag_pattern: 'release/(?P<version>[\d.]+)-payments'
+        "#;
+        let cfg: Config = serde_yaml::from_str(yaml_str).unwrap();
+        assert_eq!(
+            cfg.tag_pattern,
+            Some(r"release/(?P<version>[\d.]+)-payments".to_string())
+        );
+    }
+
+    #[test]
+    fn can_deserialize_sections_from_config_yaml() {
+        let yaml_str = r#"
+            base_url: "https://gitlab.com"
+            project_id: 1234
+            api_token:
+                from: EnvVar
+                name: API_TOKEN
+            teams: {}
+            sections:
+                - title: Features
+                  labels: [feature, enhancement]
+                  types: [feat]
+                - title: Bug fixes
+                  labels: [bug]
+        "#;
+        let cfg: Config = serde_yaml::from_str(yaml_str).unwrap();
+        assert_eq!(
+            cfg.sections,
+            vec![
+                Section {
+                    title: "Features".to_string(),
+                    labels: vec!["feature".to_string(), "enhancement".to_string()],
+                    types: vec!["feat".to_string()]
+                },
+                Section {
+                    title: "Bug fixes".to_string(),
+                    labels: vec!["bug".to_string()],
+                    types: vec![]
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn release_notes_record_walked_commits() {
+        let repo = tmp_repo();
+        let proj = Project::new("http://localhost".to_string(), 1, "token".to_string());
+
+        let initial_commit = initial_commit(&repo).unwrap();
+        lightweight_tag(&repo, initial_commit, "tag-1").unwrap();
+        let commit_2 = empty_commit(&repo).unwrap();
+        let commit_3 = empty_commit(&repo).unwrap();
+
+        let since = Since::GreatestTag {
+            pattern: TagPattern::from_prefix("tag-", &TagScheme::Numeric).unwrap(),
+            scheme: TagScheme::Numeric,
+        };
+        let notes = build_release_notes(
+            MrSource::Forge(&proj),
+            &repo,
+            &since,
+            "HEAD",
+            &matcher(MrLookup::Trailers),
+            UnmatchedCommits::List,
+            &Filter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(notes.base, "tag-1");
+        assert_eq!(notes.up_to, "HEAD");
+        assert_eq!(notes.commits, vec![commit_3, commit_2]);
+        assert!(notes.mr_ids.is_empty());
+        assert_eq!(notes.unmatched_commits, vec![commit_3, commit_2]);
+        assert!(notes.mrs.is_empty());
+        assert!(notes.filtered_out.is_empty());
+    }
+
+    #[test]
+    fn release_notes_can_be_built_from_commits_alone() {
+        let repo = tmp_repo();
+        let initial_commit = initial_commit(&repo).unwrap();
+        lightweight_tag(&repo, initial_commit, "tag-1").unwrap();
+        commit_with_message(&repo, "Fix typo\n\nSee merge request group/project!4").unwrap();
+        commit_with_message(
+            &repo,
+            "Merge branch 'export' into 'main'\n\nAdd export\n\nSee merge request group/project!5",
+        )
+        .unwrap();
+
+        let since = Since::GreatestTag {
+            pattern: TagPattern::from_prefix("tag-", &TagScheme::Numeric).unwrap(),
+            scheme: TagScheme::Numeric,
+        };
+        let notes = build_release_notes(
+            MrSource::Commits(ChangeKind::MergeRequest),
+            &repo,
+            &since,
+            "HEAD",
+            &matcher(MrLookup::TrailersThenForge),
+            UnmatchedCommits::List,
+            &Filter::default(),
+        )
+        .unwrap();
+
+        let titles: Vec<String> = notes.mrs.iter().map(|mr| mr.to_string()).collect();
+        assert_eq!(titles, vec!["Add export", "Fix typo"]);
+        assert!(notes.unmatched_commits.is_empty());
+    }
+
+    #[test]
+    fn release_notes_can_start_from_explicit_ref() {
+        let repo = tmp_repo();
+        let proj = Project::new("http://localhost".to_string(), 1, "token".to_string());
+
+        initial_commit(&repo).unwrap();
+        empty_commit(&repo).unwrap();
+        let commit_3 = empty_commit(&repo).unwrap();
+
+        let since = Since::Ref("HEAD~1".to_string());
+        let notes = build_release_notes(
+            MrSource::Forge(&proj),
+            &repo,
+            &since,
+            "HEAD",
+            &matcher(MrLookup::Trailers),
+            UnmatchedCommits::List,
+            &Filter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(notes.base, "HEAD~1");
+        assert_eq!(notes.commits, vec![commit_3]);
+    }
+
+    #[test]
+    fn release_notes_fall_back_to_asking_gitlab_about_merged_mrs_with_commits() {
+        let repo = tmp_repo();
+
+        let initial_commit = initial_commit(&repo).unwrap();
+        lightweight_tag(&repo, initial_commit, "tag-1").unwrap();
+        let squashed = commit_with_message(&repo, "Squashed change").unwrap();
+        let orphan = commit_with_message(&repo, "Pushed straight to main").unwrap();
+        commit_with_message(&repo, "Merge branch\n\nSee merge request group/proj!7").unwrap();
+
+        let squashed_path = format!(
+            "/api/v4/projects/1/repository/commits/{}/merge_requests",
+            squashed
+        );
+        // 5 merged it, 6 is still open and 8 merged it into another branch.
+        let for_squashed = json_list(vec![
+            mr(5).squash_commit(&squashed.to_string()),
+            mr(6).unmerged(),
+            mr(8).merge_commit(&"1".repeat(40)),
+        ]);
+        let base_url = mock_server(move |req| {
+            assert_eq!(req.method, "GET");
+            assert_eq!(req.header("Private-Token"), Some("token"));
+            assert!(req.body.is_empty());
+            if req.path == squashed_path {
+                MockResponse::json(&for_squashed)
+            } else if req.path.starts_with("/api/v4/projects/1/merge_requests?") {
+                assert!(req.path.ends_with("&iids[]=7"), "{}", req.path);
+                MockResponse::json(&json_list(vec![mr(7)]))
+            } else {
+                MockResponse::json("[]")
+            }
+        });
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        let since = Since::Ref("tag-1".to_string());
+        let notes = build_release_notes(
+            MrSource::Forge(&proj),
+            &repo,
+            &since,
+            "HEAD",
+            &matcher(MrLookup::TrailersThenForge),
+            UnmatchedCommits::List,
+            &Filter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(notes.mr_ids, vec![own_mr(7), own_mr(5)]);
+        let iids: Vec<u64> = notes.mrs.iter().map(|mr| mr.iid).collect();
+        assert_eq!(iids, vec![5, 7]);
+        assert_eq!(notes.unmatched_commits, vec![orphan]);
+    }
+
+    #[test]
+    fn release_notes_keep_the_project_of_mrs_found_by_commit() {
+        let repo = tmp_repo();
+
+        let initial_commit = initial_commit(&repo).unwrap();
+        lightweight_tag(&repo, initial_commit, "tag-1").unwrap();
+        let vendored = commit_with_message(&repo, "Vendored fix").unwrap();
+
+        let forge = fake_forge()
+            .mrs(|iid| mr(iid).project("group/other"))
+            .commit(&vendored.to_string(), &[4]);
+        let asked = forge.asked();
+
+        let since = Since::Ref("tag-1".to_string());
+        let notes = build_release_notes(
+            MrSource::Forge(&forge),
+            &repo,
+            &since,
+            "HEAD",
+            &matcher(MrLookup::TrailersThenForge),
+            UnmatchedCommits::List,
+            &Filter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            notes.mr_ids,
+            vec![MrRef {
+                project: Some("group/other".to_string()),
+                iid: 4
+            }]
+        );
+        assert_eq!(notes.mrs[0].reference(), "group/other!4");
+        assert!(asked.borrow().mrs.is_empty());
+    }
+
+    #[test]
+    fn release_notes_can_read_conventional_commits() {
+        let repo = tmp_repo();
+
+        let initial_commit = initial_commit(&repo).unwrap();
+        lightweight_tag(&repo, initial_commit, "tag-1").unwrap();
+        commit_with_message(&repo, "feat(ui): add dark mode").unwrap();
+        let merge = commit_with_message(&repo, "Merge it\n\nSee merge request group/project!3")
+            .unwrap();
+        let wip = commit_with_message(&repo, "wip").unwrap();
+
+        let base_url = mock_server(|_| MockResponse::json(&json_list(vec![mr(3)])));
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        let since = Since::Ref("tag-1".to_string());
+        let notes = build_release_notes(
+            MrSource::Forge(&proj),
+            &repo,
+            &since,
+            "HEAD",
+            &matcher(MrLookup::Trailers),
+            UnmatchedCommits::Conventional,
+            &Filter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(notes.mr_ids, vec![own_mr(3)]);
+        assert_eq!(notes.unmatched_commits, vec![wip]);
+        assert_eq!(notes.commit_notes.len(), 1);
+        assert_eq!(notes.commit_notes[0].commit.scope, Some("ui".to_string()));
+        assert_ne!(notes.commit_notes[0].id, merge);
+    }
+
+    #[test]
+    fn release_notes_fetch_cross_project_mrs_from_their_project() {
+        let repo = tmp_repo();
+
+        let initial_commit = initial_commit(&repo).unwrap();
+        lightweight_tag(&repo, initial_commit, "tag-1").unwrap();
+        commit_with_message(&repo, "Squashed change (!4)").unwrap();
+        commit_with_message(&repo, "Vendored fix (group/other!4)").unwrap();
+
+        let base_url = mock_server(move |req| {
+            if req.path.starts_with("/api/v4/projects/1/merge_requests?")
+                || req
+                    .path
+                    .starts_with("/api/v4/projects/group%2Fother/merge_requests?")
+            {
+                MockResponse::json(&json_list(vec![mr(4)]))
+            } else {
+                MockResponse::json("[]")
+            }
+        });
+        let proj = Project::new(base_url, 1, "token".to_string());
+
+        let patterns = vec![r"\((?P<project>[\w./-]+)?!(?P<iid>\d+)\)".to_string()];
+        let matcher = MrMatcher::new(&patterns, MrLookup::Trailers).unwrap();
+        let since = Since::Ref("tag-1".to_string());
+        let notes = build_release_notes(
+            MrSource::Forge(&proj),
+            &repo,
+            &since,
+            "HEAD",
+            &matcher,
+            UnmatchedCommits::List,
+            &Filter::default(),
+        )
+        .unwrap();
+
+        assert_eq!(
+            notes.mr_ids,
+            vec![
+                MrRef {
+                    project: Some("group/other".to_string()),
+                    iid: 4
+                },
+                own_mr(4)
+            ]
+        );
+        let references: Vec<String> = notes.mrs.iter().map(|mr| mr.reference()).collect();
+        assert_eq!(references, vec!["!4", "group/other!4"]);
+    }
+
+    #[test]
+    fn next_tag_bumps_version_inside_pattern() {
+        let since = Since::GreatestTag {
+            pattern: TagPattern::new(r"release/(?P<version>[\d.]+)-payments").unwrap(),
+            scheme: TagScheme::Dotted,
+        };
+
+        assert_eq!(
+            since
+                .next_tag("release/2024.03.1-payments", Bump::Patch)
+                .unwrap(),
+            "release/2024.03.2-payments"
+        );
+    }
+
+    #[test]
+    fn releases_start_from_the_tag_before_them() {
+        let repo = tmp_repo();
+        let tags = ["v1.0.0", "v1.1.0", "v1.2.0"];
+        let root = initial_commit(&repo).unwrap();
+        lightweight_tag(&repo, root, tags[0]).unwrap();
+        for tag in &tags[1..] {
+            empty_commit(&repo).unwrap();
+            lightweight_tag(&repo, empty_commit(&repo).unwrap(), tag).unwrap();
+        }
+        let scheme = TagScheme::from_name("semver", false).unwrap();
+        let since = Since::GreatestTag {
+            pattern: TagPattern::from_prefix("v", &scheme).unwrap(),
+            scheme,
+        };
+        let base = |since: &Since, tag| match since.before_release(&repo, tag) {
+            Ok(Since::Ref(base)) => Ok(base),
+            Ok(_) => panic!("A release's base should be a ref"),
+            Err(e) => Err(e),
+        };
+
+        assert_eq!(base(&since, "v1.1.0").unwrap(), "v1.0.0");
+        assert_eq!(base(&since, "v1.2.0").unwrap(), "v1.1.0");
+        assert_eq!(base(&since, "v1.0.0").unwrap(), root.to_string());
+        assert!(base(&since, "v0.9.0").is_err());
+        assert_eq!(
+            base(&Since::Ref("v1.0.0".to_string()), "v1.1.0").unwrap(),
+            "v1.0.0"
+        );
+        assert!(base(&Since::Ref("v1.2.0".to_string()), "v1.1.0").is_err());
+        assert!(base(&Since::Ref("v1.1.0".to_string()), "v1.1.0").is_err());
+    }
+
+    #[test]
+    fn first_release_starts_from_the_root_commit() {
+        let repo = tmp_repo();
+        let root = initial_commit(&repo).unwrap();
+        let change = empty_commit(&repo).unwrap();
+        lightweight_tag(&repo, change, "v1.0.0").unwrap();
+        let scheme = TagScheme::from_name("semver", false).unwrap();
+        let since = Since::NearestTag {
+            pattern: TagPattern::from_prefix("v", &scheme).unwrap(),
+            scheme,
+        };
+
+        let base = match since.before_release(&repo, "v1.0.0").unwrap() {
+            Since::Ref(base) => base,
+            _ => panic!("A release's base should be a ref"),
+        };
+        assert_eq!(base, root.to_string());
+
+        let notes = build_release_notes(
+            MrSource::Commits(ChangeKind::MergeRequest),
+            &repo,
+            &Since::Ref(base),
+            "v1.0.0",
+            &matcher(MrLookup::Trailers),
+            UnmatchedCommits::List,
+            &Filter::default(),
+        )
+        .unwrap();
+        assert_eq!(notes.commits, vec![change]);
+    }
+
+    #[test]
+    fn next_tag_needs_a_tag_pattern() {
+        let since = Since::Ref("main".to_string());
+
+        assert!(since.next_tag("main", Bump::Patch).is_err());
+    }
+
+    #[test]
+    fn mr_pattern_without_iid_group_is_an_error() {
+        let patterns = vec![r"!(\d+)".to_string()];
+
+        assert_eq!(
+            MrMatcher::new(&patterns, MrLookup::Trailers)
+                .unwrap_err()
+                .to_string(),
+            r"MR pattern has no (?P<iid>...) group: !(\d+)"
+        );
+    }
+
+    fn matcher(lookup: MrLookup) -> MrMatcher {
+        MrMatcher::new(&[DEFAULT_MR_PATTERN.to_string()], lookup).unwrap()
+    }
+
+    fn own_mr(iid: u64) -> MrRef {
+        MrRef { project: None, iid }
+    }
 }
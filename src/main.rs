@@ -1,51 +1,661 @@
+use chrono::Local;
 use git2::Repository;
+use shippy::cache::{cache_dir, CachedForge};
+use shippy::config;
 use shippy::err::CliError;
-use shippy::git_lab::Project;
-use shippy::{print_release_notes, Config};
+use shippy::filter::Filter;
+use shippy::forge::Forge;
+use shippy::next_version::suggest_bump;
+use shippy::render::{self, Format, MrLinks, TemplateNotes};
+use shippy::sections::{default_sections, group_by_section, Section};
+use shippy::tags::{Bump, TagPattern, TagScheme};
+use shippy::{
+    build_release_notes, create_tag, push_tag, Config, MrLookup, MrMatcher, MrSource, ReleaseNotes,
+    Since, UnmatchedCommits,
+};
 use std::env;
-use std::fs::File;
+use std::fs;
 use structopt::StructOpt;
 use std::path::PathBuf;
+use std::time::Duration;
 
 extern crate shippy;
 
 #[derive(StructOpt)]
 #[structopt(name = "shippy", about = "Release note generator")]
 struct Opts {
+    /// Tags are this prefix followed by a version, used instead of the config file's tag_pattern.
+    /// Leave it out when --tag_pattern or --since is given, the first argument is then up_to, as
+    /// it is when it's the only argument and the config file has a tag_pattern.
     #[structopt(index = 1)]
-    tag_prefix: String,
+    tag_prefix: Option<String>,
 
-    #[structopt(index = 2, default_value = "HEAD")]
-    up_to: String,
+    /// Defaults to HEAD
+    #[structopt(index = 2)]
+    up_to: Option<String>,
 
-    #[structopt(short = "c", long = "config_file", parse(from_os_str), default_value = "./shippy.yml")]
-    config_file: PathBuf,
+    /// Used instead of the nearest shippy.yml, from here up to the repo's root. Either is layered
+    /// over ~/.config/shippy/config.yml, and SHIPPY_* variables over both
+    #[structopt(short = "c", long = "config_file", parse(from_os_str), global = true)]
+    config_file: Option<PathBuf>,
 
-    #[structopt(short = "t", long = "team")]
-    team: Option<String>
+    /// The remote to find the forge and project from, when the config file doesn't say, and to
+    /// push new tags to
+    #[structopt(long = "remote", default_value = "origin", global = true)]
+    remote: String,
+
+    /// Only merge requests by this team's members, the same as --include team:<team>
+    #[structopt(short = "t", long = "team", global = true)]
+    team: Option<String>,
+
+    /// Only merge requests matching this, e.g. label:bug, target_branch:main or author:alice.
+    /// Can be repeated
+    #[structopt(long = "include", number_of_values = 1, global = true)]
+    include: Vec<String>,
+
+    /// Leave out merge requests matching this, e.g. label:chore, draft or title:^Revert. Can be
+    /// repeated
+    #[structopt(long = "exclude", number_of_values = 1, global = true)]
+    exclude: Vec<String>,
+
+    /// List the merge requests that were filtered out, and why
+    #[structopt(short = "v", long = "verbose", global = true)]
+    verbose: bool,
+
+    #[structopt(
+        long = "tag_scheme",
+        default_value = "numeric",
+        possible_values = &["numeric", "semver", "dotted"],
+        global = true
+    )]
+    tag_scheme: String,
+
+    /// A regex for tag names, with the version in a (?P<version>...) group
+    #[structopt(short = "p", long = "tag_pattern", global = true)]
+    tag_pattern: Option<String>,
+
+    /// Use the closest tag reachable from up_to, rather than the greatest tag in the repo
+    #[structopt(long = "nearest_tag", global = true)]
+    nearest_tag: bool,
+
+    /// Start from this ref instead of looking for a tag
+    #[structopt(short = "s", long = "since", conflicts_with = "nearest_tag", global = true)]
+    since: Option<String>,
+
+    /// Let semver pre-release tags (e.g. v1.5.0-rc.1) be picked as the base tag
+    #[structopt(long = "include_pre_releases", global = true)]
+    include_pre_releases: bool,
+
+    /// Ask the forge which merged merge requests contain commits that have no merge request
+    /// trailer
+    #[structopt(long = "lookup_commits", global = true)]
+    lookup_commits: bool,
+
+    /// Don't use the forge at all, merge requests are only what their commits say about them
+    #[structopt(long = "offline", global = true)]
+    offline: bool,
+
+    /// Fetch merge requests again even when they're cached
+    #[structopt(long = "refresh", global = true)]
+    refresh: bool,
+
+    /// Put commits without a merge request in the notes when they're conventional commits
+    #[structopt(long = "conventional_commits", global = true)]
+    conventional_commits: bool,
+
+    /// Defaults to template when there's a template to use, otherwise plain
+    #[structopt(
+        short = "f",
+        long = "format",
+        possible_values = &["plain", "markdown", "json", "template"],
+        global = true
+    )]
+    format: Option<String>,
+
+    /// A handlebars template to render the notes with, overrides the one in the config file
+    #[structopt(long = "template", parse(from_os_str), global = true)]
+    template: Option<PathBuf>,
+
+    /// Heading for markdown output, defaults to "Changes since <base>"
+    #[structopt(long = "title", global = true)]
+    title: Option<String>,
+
+    #[structopt(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(StructOpt)]
+enum Command {
+    /// Create or update the GitLab release for a tag, with the notes up to that tag as its
+    /// description
+    #[structopt(name = "publish")]
+    Publish {
+        tag: String,
+
+        /// Print the request instead of sending it
+        #[structopt(long = "dry_run")]
+        dry_run: bool,
+    },
+    /// Tag up_to with the version after the base tag, and print the new tag
+    #[structopt(name = "tag")]
+    Tag {
+        #[structopt(long = "bump", default_value = "patch", possible_values = &["major", "minor", "patch"])]
+        bump: String,
+
+        /// Make an annotated tag, with the release notes as its message
+        #[structopt(long = "annotate")]
+        annotate: bool,
+
+        /// Push the new tag to --remote
+        #[structopt(long = "push")]
+        push: bool,
+
+        /// Print the tag instead of creating it
+        #[structopt(long = "dry_run")]
+        dry_run: bool,
+    },
+    /// Suggest the tag after the base tag from the merge requests since it, and list the ones
+    /// that decided the version bump
+    #[structopt(name = "next-version")]
+    NextVersion,
 }
 
 fn main() -> Result<(), CliError<'static>> {
     let cwd = env::current_dir().map_err(|e| CliError::Io("Could not get current_dir", e))?;
     let opts = Opts::from_args();
 
-    let cfg_file =
-        File::open(opts.config_file).map_err(|e| CliError::Io("Could not open config file", e))?;
+    let repo =
+        &Repository::discover(&cwd).map_err(|e| CliError::Git("Could not open repository", e))?;
+
+    let layers = config::layers(opts.config_file.as_deref(), &cwd, repo.workdir())?;
+    let mut cfg: Config = config::load(&layers, env::vars())?;
+
+    let template_path = opts.template.or_else(|| cfg.template.clone());
+    let format = match (opts.format.as_ref(), template_path.as_ref()) {
+        (Some(name), _) => Format::from_name(name.as_str())?,
+        (None, Some(_)) => Format::Template,
+        (None, None) if opts.command.is_some() => Format::Markdown,
+        (None, None) => Format::Plain,
+    };
+    let renders_notes = matches!(
+        opts.command,
+        Some(Command::Publish { .. }) | Some(Command::Tag { .. })
+    );
+    if renders_notes && (format == Format::Plain || format == Format::Json) {
+        return Err(CliError::Str(
+            "Can only use markdown or template output as release notes",
+        ));
+    }
+    let template = match (format, template_path) {
+        (Format::Template, Some(path)) => Some(
+            fs::read_to_string(path).map_err(|e| CliError::Io("Could not read template", e))?,
+        ),
+        (Format::Template, None) => {
+            return Err(CliError::Str(
+                "No --template given, and no template in the config file",
+            ))
+        }
+        _ => None,
+    };
+    let tag_scheme = TagScheme::from_name(opts.tag_scheme.as_str(), opts.include_pre_releases)?;
+    let TagArgs {
+        pattern,
+        tag_prefix,
+        up_to,
+    } = tag_args(
+        opts.tag_pattern,
+        opts.since.is_some(),
+        cfg.tag_pattern.clone(),
+        opts.tag_prefix,
+        opts.up_to,
+    )?;
+    let up_to = match (&opts.command, up_to) {
+        (Some(Command::Publish { tag, .. }), None) => tag.clone(),
+        (Some(Command::Publish { .. }), Some(_)) => {
+            return Err(CliError::Str(
+                "Can't give up_to when publishing, the notes go up to the release's tag",
+            ))
+        }
+        (_, up_to) => up_to.unwrap_or_else(|| "HEAD".to_string()),
+    };
 
-    let cfg: Config = serde_yaml::from_reader(cfg_file)
-        .map_err(|e| CliError::Yaml("Could not deserialize config file", e))?;
+    let since = if let Some(since) = opts.since {
+        Since::Ref(since)
+    } else {
+        let pattern = match (pattern, tag_prefix) {
+            (Some(pattern), _) => TagPattern::new(pattern.as_str())?,
+            (None, Some(prefix)) => TagPattern::from_prefix(prefix.as_str(), &tag_scheme)?,
+            (None, None) => {
+                return Err(CliError::Str(
+                    "No tag prefix given, and no tag_pattern in the config file",
+                ))
+            }
+        };
+        let scheme = tag_scheme;
+        if opts.nearest_tag {
+            Since::NearestTag { pattern, scheme }
+        } else {
+            Since::GreatestTag { pattern, scheme }
+        }
+    };
 
-    let proj = Project::new(cfg.base_url.clone(), cfg.project_id, cfg.api_token.get()?);
+    let since = match &opts.command {
+        Some(Command::Publish { tag, .. }) => since.before_release(repo, tag.as_str())?,
+        _ => since,
+    };
 
-    let repo = &Repository::open(cwd).map_err(|e| CliError::Git("Could not open repository", e))?;
+    let next_tag = match &opts.command {
+        Some(Command::Tag { bump, .. }) => {
+            let base = since.resolve(repo, up_to.as_str())?;
+            since.next_tag(base.as_str(), Bump::from_name(bump.as_str())?)?
+        }
+        _ => String::new(),
+    };
+    let push_to = match &opts.command {
+        Some(Command::Tag { push: true, .. }) => Some(opts.remote.as_str()),
+        _ => None,
+    };
+    if let Some(Command::Tag {
+        annotate: false,
+        dry_run,
+        ..
+    }) = &opts.command
+    {
+        return tag_release(repo, next_tag.as_str(), up_to.as_str(), None, push_to, *dry_run);
+    }
 
-    let authors = opts.team.and_then(|t: String| cfg.teams.get(t.as_str()));
+    // Built after a plain tag is made, so that doesn't need an api token.
+    let forge = if opts.offline {
+        None
+    } else {
+        cfg.fill_from_remote(repo, opts.remote.as_str())?;
+        Some(cached(cfg.forge()?, &cfg, opts.refresh))
+    };
 
-    print_release_notes(
-        &proj,
+    let mut include = cfg.filter.include.clone();
+    include.extend(opts.team.iter().map(|team| format!("team:{}", team)));
+    include.extend(opts.include.iter().cloned());
+    let mut exclude = cfg.filter.exclude.clone();
+    exclude.extend(opts.exclude.iter().cloned());
+    let filter = Filter::new(&include, &exclude, &cfg.teams, forge.as_deref())?;
+
+    let mr_lookup = if opts.lookup_commits {
+        MrLookup::TrailersThenForge
+    } else {
+        MrLookup::Trailers
+    };
+
+    let mr_patterns = cfg
+        .mr_patterns
+        .clone()
+        .unwrap_or_else(|| cfg.forge.default_mr_patterns());
+    let mr_matcher = MrMatcher::new(&mr_patterns, mr_lookup)?;
+
+    let unmatched = if opts.conventional_commits || cfg.conventional_commits {
+        UnmatchedCommits::Conventional
+    } else {
+        UnmatchedCommits::List
+    };
+    let defaults;
+    let sections = if cfg.sections.is_empty() && unmatched == UnmatchedCommits::Conventional {
+        defaults = default_sections();
+        &defaults
+    } else {
+        &cfg.sections
+    };
+
+    let source = match &forge {
+        Some(forge) => MrSource::Forge(forge.as_ref()),
+        None => MrSource::Commits(cfg.forge.change_kind()),
+    };
+    let notes = build_release_notes(
+        source,
         repo,
-        opts.tag_prefix.as_str(),
-        opts.up_to.as_str(),
-        authors
-    )
+        &since,
+        up_to.as_str(),
+        &mr_matcher,
+        unmatched,
+        &filter,
+    )?;
+
+    if let Some(Command::NextVersion) = opts.command {
+        eprint!("{}", summary(&notes, &filter, opts.verbose));
+        let suggestion = suggest_bump(&notes.mrs, &notes.commit_notes);
+        println!("{}", since.next_tag(notes.base.as_str(), suggestion.bump)?);
+        if suggestion.because_of.is_empty() && suggestion.commits_because_of.is_empty() {
+            println!("{} bump, there are no merge requests", suggestion.bump);
+        } else {
+            println!("{} bump because of:", suggestion.bump);
+        }
+        for mr in suggestion.because_of {
+            println!("{} {}", mr.reference(), mr);
+        }
+        for note in suggestion.commits_because_of {
+            println!("{:.8} {}", note.id.to_string(), note.commit);
+        }
+        return Ok(());
+    }
+
+    if format == Format::Plain {
+        print!("{}", summary(&notes, &filter, opts.verbose));
+        print_release_notes(&notes, sections, repo);
+        return Ok(());
+    }
+
+    // Keep stdout to just the notes so it can be piped into other tools.
+    eprint!("{}", summary(&notes, &filter, opts.verbose));
+    let links = match &forge {
+        Some(forge) => forge.links()?,
+        None => MrLinks::unlinked(),
+    };
+    let rendered = match format {
+        Format::Markdown => {
+            let title = opts
+                .title
+                .unwrap_or_else(|| format!("Changes since {}", notes.base));
+            render::markdown(title.as_str(), &notes, sections, &links)
+        }
+        Format::Json => render::json(&notes, &links)? + "\n",
+        Format::Template => {
+            let date = Local::now().format("%Y-%m-%d").to_string();
+            let context = TemplateNotes::new(&notes, sections, &links, date.as_str());
+            let template = template.unwrap_or_default();
+            render::template(template.as_str(), &context)?
+        }
+        Format::Plain => unreachable!(),
+    };
+
+    match opts.command {
+        None => print!("{}", rendered),
+        Some(Command::Publish { tag, dry_run }) => {
+            let forge = forge.ok_or(CliError::Str("Can't publish a release --offline"))?;
+            let request = forge.release_request(tag.as_str(), rendered.as_str())?;
+            if dry_run {
+                println!("{}", request);
+            } else {
+                forge.send(&request)?;
+                eprintln!("Published release {}", tag);
+            }
+        }
+        Some(Command::Tag { dry_run, .. }) => {
+            let message = Some(rendered.as_str());
+            tag_release(repo, next_tag.as_str(), up_to.as_str(), message, push_to, dry_run)?;
+        }
+        Some(Command::NextVersion) => unreachable!(),
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq)]
+struct TagArgs {
+    pattern: Option<String>,
+    tag_prefix: Option<String>,
+    up_to: Option<String>,
+}
+
+/// Works out the tag pattern, tag prefix and up_to from the positional arguments. The first is a
+/// tag prefix unless `--tag_pattern` or `--since` is given, or it's the only one and the config
+/// file has a pattern. A prefix wins over the config file's pattern.
+fn tag_args(
+    cli_pattern: Option<String>,
+    since_given: bool,
+    cfg_pattern: Option<String>,
+    first: Option<String>,
+    second: Option<String>,
+) -> Result<TagArgs, CliError<'static>> {
+    if cli_pattern.is_some() || since_given {
+        return match second {
+            Some(_) => Err(CliError::Str(
+                "Can't use a tag prefix when --tag_pattern or --since is given",
+            )),
+            None => Ok(TagArgs {
+                pattern: cli_pattern,
+                tag_prefix: None,
+                up_to: first,
+            }),
+        };
+    }
+
+    Ok(match (first, second) {
+        (Some(prefix), Some(up_to)) => TagArgs {
+            pattern: None,
+            tag_prefix: Some(prefix),
+            up_to: Some(up_to),
+        },
+        (Some(up_to), None) if cfg_pattern.is_some() => TagArgs {
+            pattern: cfg_pattern,
+            tag_prefix: None,
+            up_to: Some(up_to),
+        },
+        (Some(prefix), None) => TagArgs {
+            pattern: None,
+            tag_prefix: Some(prefix),
+            up_to: None,
+        },
+        (None, second) => TagArgs {
+            pattern: cfg_pattern,
+            tag_prefix: None,
+            up_to: second,
+        },
+    })
+}
+
+fn tag_release(
+    repo: &Repository,
+    tag: &str,
+    target: &str,
+    message: Option<&str>,
+    push_to: Option<&str>,
+    dry_run: bool,
+) -> Result<(), CliError<'static>> {
+    if dry_run {
+        eprintln!("Would tag {} as {}", target, tag);
+        if let Some(message) = message {
+            eprintln!("{}", message);
+        }
+    } else {
+        create_tag(repo, tag, target, message)?;
+        if let Some(remote) = push_to {
+            push_tag(repo, remote, tag)?;
+        }
+    }
+
+    println!("{}", tag);
+    Ok(())
+}
+
+/// Wraps `forge` in the merge request cache, unless it's turned off or there's nowhere to put it.
+fn cached(forge: Box<dyn Forge>, cfg: &Config, refresh: bool) -> Box<dyn Forge> {
+    match (cache_dir(), &cfg.base_url, &cfg.project_id) {
+        (Some(dir), Some(base_url), Some(project_id)) if cfg.cache_ttl_hours > 0 => {
+            Box::new(CachedForge::new(
+                forge,
+                dir.as_path(),
+                base_url.as_str(),
+                project_id.to_string().as_str(),
+                Duration::from_secs(cfg.cache_ttl_hours * 60 * 60),
+                Duration::from_secs(cfg.group_cache_ttl_hours * 60 * 60),
+                refresh,
+            ))
+        }
+        _ => forge,
+    }
+}
+
+fn summary(notes: &ReleaseNotes, filter: &Filter, verbose: bool) -> String {
+    let mut summary = format!("Searching between {} and {}\n", notes.base, notes.up_to);
+    summary.push_str(
+        format!(
+            "Found {} commits, pointing to {} merge requests:\n",
+            notes.commits.len(),
+            notes.mr_ids.len()
+        )
+        .as_str(),
+    );
+
+    if !filter.is_empty() {
+        summary.push_str(
+            format!(
+                "Filtering {}, which leaves out {} merge requests\n",
+                filter,
+                notes.filtered_out.len()
+            )
+            .as_str(),
+        );
+    }
+    if verbose {
+        for filtered in &notes.filtered_out {
+            summary.push_str(
+                format!(
+                    "  {} {}: {}\n",
+                    filtered.mr.reference(),
+                    filtered.mr,
+                    filtered.reason
+                )
+                .as_str(),
+            );
+        }
+    }
+
+    summary
+}
+
+fn print_release_notes(notes: &ReleaseNotes, sections: &[Section], repo: &Repository) {
+    if sections.is_empty() {
+        for mr in &notes.mrs {
+            println!("{}", mr)
+        }
+        for note in &notes.commit_notes {
+            println!("{:.8} {}", note.id.to_string(), note.commit)
+        }
+    } else {
+        for section in group_by_section(&notes.mrs, &notes.commit_notes, sections) {
+            println!();
+            println!("{}:", section.title);
+            for mr in section.mrs {
+                println!("{}", mr)
+            }
+            for note in section.commits {
+                println!("{:.8} {}", note.id.to_string(), note.commit)
+            }
+        }
+    }
+
+    if !notes.unmatched_commits.is_empty() {
+        println!(
+            "{} commits didn't point to a merge request:",
+            notes.unmatched_commits.len()
+        );
+        for oid in &notes.unmatched_commits {
+            let summary = repo
+                .find_commit(*oid)
+                .ok()
+                .and_then(|c| c.summary().map(str::to_string))
+                .unwrap_or_default();
+            println!("{:.8} {}", oid.to_string(), summary);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tag_args, Command, Opts, TagArgs};
+    use structopt::StructOpt;
+
+    fn some(s: &str) -> Option<String> {
+        Some(s.to_string())
+    }
+
+    #[test]
+    fn cli_prefix_wins_over_config_pattern() {
+        let args = tag_args(None, false, some("^v(?P<version>.*)$"), some("v1"), some("HEAD~1"));
+
+        let expected = TagArgs {
+            pattern: None,
+            tag_prefix: some("v1"),
+            up_to: some("HEAD~1"),
+        };
+        assert_eq!(args.unwrap(), expected);
+    }
+
+    #[test]
+    fn config_pattern_is_used_without_a_cli_prefix() {
+        let args = tag_args(None, false, some("^v(?P<version>.*)$"), None, None);
+
+        let expected = TagArgs {
+            pattern: some("^v(?P<version>.*)$"),
+            tag_prefix: None,
+            up_to: None,
+        };
+        assert_eq!(args.unwrap(), expected);
+    }
+
+    #[test]
+    fn only_argument_is_up_to_with_config_pattern() {
+        let args = tag_args(None, false, some("^v(?P<version>.*)$"), some("HEAD~3"), None);
+
+        let expected = TagArgs {
+            pattern: some("^v(?P<version>.*)$"),
+            tag_prefix: None,
+            up_to: some("HEAD~3"),
+        };
+        assert_eq!(args.unwrap(), expected);
+
+        let without_config = tag_args(None, false, None, some("v"), None);
+        assert_eq!(without_config.unwrap().tag_prefix, some("v"));
+    }
+
+    #[test]
+    fn first_argument_is_up_to_with_cli_pattern() {
+        let args = tag_args(some("^v(?P<version>.*)$"), false, None, some("HEAD~1"), None);
+
+        let expected = TagArgs {
+            pattern: some("^v(?P<version>.*)$"),
+            tag_prefix: None,
+            up_to: some("HEAD~1"),
+        };
+        assert_eq!(args.unwrap(), expected);
+    }
+
+    #[test]
+    fn prefix_with_since_is_an_error() {
+        assert!(tag_args(None, true, None, some("v"), some("HEAD")).is_err());
+    }
+
+    #[test]
+    fn options_go_before_or_after_the_subcommand() {
+        let parse = |args: &[&str]| Opts::from_iter_safe(args).unwrap();
+
+        let before = parse(&[
+            "shippy",
+            "--remote",
+            "upstream",
+            "--tag_scheme",
+            "semver",
+            "v",
+            "tag",
+            "--push",
+        ]);
+        let after = parse(&[
+            "shippy",
+            "v",
+            "tag",
+            "--push",
+            "--remote",
+            "upstream",
+            "--tag_scheme",
+            "semver",
+        ]);
+
+        for opts in &[before, after] {
+            assert_eq!(opts.remote, "upstream");
+            assert_eq!(opts.tag_scheme, "semver");
+            assert_eq!(opts.tag_prefix.as_deref(), Some("v"));
+            assert!(matches!(opts.command, Some(Command::Tag { push: true, .. })));
+        }
+        assert_eq!(parse(&["shippy", "v", "next-version"]).remote, "origin");
+        let since = parse(&["shippy", "publish", "v1.1.0", "--since", "v1.0.0"]);
+        assert_eq!(since.since.as_deref(), Some("v1.0.0"));
+    }
 }
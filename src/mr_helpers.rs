@@ -0,0 +1,175 @@
+#[cfg(test)]
+#[allow(clippy::module_inception)]
+pub mod mr_helpers {
+    use crate::forge::{ChangeKind, MergeRequest, Milestone, User};
+    use serde_json::{json, Value};
+
+    /// Builds a [`MergeRequest`] for tests, see [`mr`].
+    pub struct MrBuilder {
+        mr: MergeRequest,
+    }
+
+    /// A merge request titled `MR <iid>` by alice, with nothing else set.
+    pub fn mr(iid: u64) -> MrBuilder {
+        MrBuilder {
+            mr: MergeRequest {
+                iid,
+                title: format!("MR {}", iid),
+                description: String::new(),
+                author: Some(User {
+                    id: 1,
+                    name: "Alice".to_string(),
+                    username: "alice".to_string(),
+                }),
+                labels: Vec::new(),
+                milestone: None,
+                target_branch: None,
+                draft: false,
+                merged: true,
+                merge_commit_sha: None,
+                squash_commit_sha: None,
+                project: None,
+                kind: ChangeKind::MergeRequest,
+            },
+        }
+    }
+
+    impl MrBuilder {
+        pub fn title(mut self, title: &str) -> MrBuilder {
+            self.mr.title = title.to_string();
+            self
+        }
+
+        pub fn description(mut self, description: &str) -> MrBuilder {
+            self.mr.description = description.to_string();
+            self
+        }
+
+        /// The author's name is their username.
+        pub fn author(mut self, username: &str) -> MrBuilder {
+            self.mr.author = Some(User {
+                id: 1,
+                name: username.to_string(),
+                username: username.to_string(),
+            });
+            self
+        }
+
+        /// Like a merge request only known from a commit message, with `--offline`.
+        pub fn no_author(mut self) -> MrBuilder {
+            self.mr.author = None;
+            self
+        }
+
+        pub fn labels(mut self, labels: &[&str]) -> MrBuilder {
+            self.mr.labels = labels.iter().map(|l| l.to_string()).collect();
+            self
+        }
+
+        pub fn milestone(mut self, title: &str) -> MrBuilder {
+            self.mr.milestone = Some(Milestone {
+                title: title.to_string(),
+            });
+            self
+        }
+
+        pub fn target_branch(mut self, branch: &str) -> MrBuilder {
+            self.mr.target_branch = Some(branch.to_string());
+            self
+        }
+
+        pub fn draft(mut self) -> MrBuilder {
+            self.mr.draft = true;
+            self
+        }
+
+        /// Still open, or closed without being merged.
+        pub fn unmerged(mut self) -> MrBuilder {
+            self.mr.merged = false;
+            self
+        }
+
+        pub fn merge_commit(mut self, sha: &str) -> MrBuilder {
+            self.mr.merge_commit_sha = Some(sha.to_string());
+            self
+        }
+
+        /// From another project, `group/project`.
+        pub fn project(mut self, project: &str) -> MrBuilder {
+            self.mr.project = Some(project.to_string());
+            self
+        }
+
+        pub fn pull_request(mut self) -> MrBuilder {
+            self.mr.kind = ChangeKind::PullRequest;
+            self
+        }
+
+        pub fn squash_commit(mut self, sha: &str) -> MrBuilder {
+            self.mr.squash_commit_sha = Some(sha.to_string());
+            self
+        }
+
+        pub fn build(self) -> MergeRequest {
+            self.mr
+        }
+
+        /// The merge request as its forge's api has it, GitLab's json for merge requests and
+        /// GitHub's or Gitea's for pull requests. Pull requests need an author, and merge into
+        /// main unless they say otherwise.
+        pub fn json(self) -> String {
+            self.value().to_string()
+        }
+
+        fn value(self) -> Value {
+            let mr = self.mr;
+            let milestone = mr.milestone.map(|m| json!({ "title": m.title }));
+            match mr.kind {
+                ChangeKind::MergeRequest => json!({
+                    "iid": mr.iid,
+                    "title": mr.title,
+                    "description": mr.description,
+                    "author": mr.author.map(|a| json!({
+                        "id": a.id,
+                        "name": a.name,
+                        "username": a.username,
+                    })),
+                    "labels": mr.labels,
+                    "milestone": milestone,
+                    "target_branch": mr.target_branch,
+                    "draft": mr.draft,
+                    "state": if mr.merged { "merged" } else { "opened" },
+                    "merge_commit_sha": mr.merge_commit_sha,
+                    "squash_commit_sha": mr.squash_commit_sha,
+                }),
+                ChangeKind::PullRequest => {
+                    let author = mr.author.expect("Pull requests always have an author");
+                    let mut user = json!({ "id": author.id, "login": author.username });
+                    // Only Gitea has display names, GitHub's json leaves them out.
+                    if author.name != author.username {
+                        user["full_name"] = json!(author.name);
+                    }
+                    let labels: Vec<Value> =
+                        mr.labels.iter().map(|l| json!({ "name": l })).collect();
+                    json!({
+                        "number": mr.iid,
+                        "title": mr.title,
+                        "body": mr.description,
+                        "user": user,
+                        "labels": labels,
+                        "milestone": milestone,
+                        "base": { "ref": mr.target_branch.as_deref().unwrap_or("main") },
+                        "draft": mr.draft,
+                        "merged_at": if mr.merged { Some("2024-01-01T00:00:00Z") } else { None },
+                        "merge_commit_sha": mr.merge_commit_sha,
+                    })
+                }
+            }
+        }
+    }
+
+    /// A json array of the merge requests, like a page of results.
+    pub fn json_list(mrs: Vec<MrBuilder>) -> String {
+        Value::Array(mrs.into_iter().map(MrBuilder::value).collect()).to_string()
+    }
+}
@@ -0,0 +1,166 @@
+use crate::conventional::{self, CommitNote, ConventionalCommit};
+use crate::forge::MergeRequest;
+use crate::tags::Bump;
+
+pub const BREAKING_LABEL: &str = "breaking";
+pub const FEATURE_LABEL: &str = "feature";
+
+/// The bump a release needs, and the merge requests and commits that asked for that much of one.
+#[derive(Debug, PartialEq)]
+pub struct Suggestion<'a> {
+    pub bump: Bump,
+    pub because_of: Vec<&'a MergeRequest>,
+    pub commits_because_of: Vec<&'a CommitNote>,
+}
+
+/// Major for a `breaking` label, a `!` after the conventional commit type or a
+/// `BREAKING CHANGE:` anywhere in the title or description. Minor for a `feature` label or a
+/// `feat` type. Patch for anything else.
+pub fn bump_for(mr: &MergeRequest) -> Bump {
+    let has_label = |label: &str| mr.labels.iter().any(|l| l == label);
+    let conventional = conventional::parse(mr.title.as_str());
+
+    if has_label(BREAKING_LABEL)
+        || matches!(conventional, Some(ConventionalCommit { breaking: true, .. }))
+        || mr.title.contains("BREAKING CHANGE:")
+        || mr.description.contains("BREAKING CHANGE:")
+    {
+        Bump::Major
+    } else if has_label(FEATURE_LABEL) {
+        Bump::Minor
+    } else {
+        conventional.as_ref().map_or(Bump::Patch, bump_for_commit)
+    }
+}
+
+/// Major for breaking changes, minor for `feat` and patch for anything else.
+pub fn bump_for_commit(commit: &ConventionalCommit) -> Bump {
+    if commit.breaking {
+        Bump::Major
+    } else if commit.commit_type == "feat" {
+        Bump::Minor
+    } else {
+        Bump::Patch
+    }
+}
+
+pub fn suggest_bump<'a>(mrs: &'a [MergeRequest], commits: &'a [CommitNote]) -> Suggestion<'a> {
+    let bump = mrs
+        .iter()
+        .map(bump_for)
+        .chain(commits.iter().map(|note| bump_for_commit(&note.commit)))
+        .max()
+        .unwrap_or(Bump::Patch);
+
+    Suggestion {
+        bump,
+        because_of: mrs.iter().filter(|mr| bump_for(mr) == bump).collect(),
+        commits_because_of: commits
+            .iter()
+            .filter(|note| bump_for_commit(&note.commit) == bump)
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conventional::{parse, CommitNote};
+    use crate::mr_helpers::mr_helpers::mr;
+    use crate::next_version::{bump_for, suggest_bump};
+    use crate::tags::Bump;
+    use git2::Oid;
+
+    #[test]
+    fn breaking_changes_are_major() {
+        assert_eq!(
+            bump_for(&mr(1).title("Drop v1 api").labels(&["breaking"]).build()),
+            Bump::Major
+        );
+        assert_eq!(
+            bump_for(&mr(1).title("feat!: drop v1 api").build()),
+            Bump::Major
+        );
+        assert_eq!(
+            bump_for(&mr(1).title("fix(api)!: drop v1 api").build()),
+            Bump::Major
+        );
+        assert_eq!(
+            bump_for(&mr(1).title("feat!: drop v1 api").labels(&["feature"]).build()),
+            Bump::Major
+        );
+        assert_eq!(
+            bump_for(&mr(1).title("BREAKING CHANGE: drop v1 api").build()),
+            Bump::Major
+        );
+
+        let described = mr(1)
+            .title("Drop v1 api")
+            .description("BREAKING CHANGE: v1 clients stop working")
+            .build();
+        assert_eq!(bump_for(&described), Bump::Major);
+    }
+
+    #[test]
+    fn features_are_minor() {
+        assert_eq!(
+            bump_for(&mr(1).title("Add export").labels(&["feature"]).build()),
+            Bump::Minor
+        );
+        assert_eq!(
+            bump_for(&mr(1).title("feat: add export").build()),
+            Bump::Minor
+        );
+        assert_eq!(
+            bump_for(&mr(1).title("feat(ui): add export").build()),
+            Bump::Minor
+        );
+    }
+
+    #[test]
+    fn everything_else_is_patch() {
+        assert_eq!(
+            bump_for(&mr(1).title("fix: typo").labels(&["bug"]).build()),
+            Bump::Patch
+        );
+        assert_eq!(
+            bump_for(&mr(1).title("features: plural isn't feat").build()),
+            Bump::Patch
+        );
+        assert_eq!(bump_for(&mr(1).title("Tidy up").build()), Bump::Patch);
+    }
+
+    #[test]
+    fn suggestion_is_the_largest_bump_with_its_mrs() {
+        let mrs = vec![
+            mr(1).title("fix: typo").build(),
+            mr(2).title("feat: add export").build(),
+            mr(3).title("Add import").labels(&["feature"]).build(),
+        ];
+
+        let suggestion = suggest_bump(&mrs, &[]);
+
+        assert_eq!(suggestion.bump, Bump::Minor);
+        let iids: Vec<u64> = suggestion.because_of.iter().map(|mr| mr.iid).collect();
+        assert_eq!(iids, vec![2, 3]);
+    }
+
+    #[test]
+    fn conventional_commits_count_towards_the_bump() {
+        let mrs = vec![mr(1).title("feat: add export").build()];
+        let commits = vec![CommitNote {
+            id: Oid::zero(),
+            commit: parse("fix(api)!: drop v1").unwrap(),
+        }];
+
+        let suggestion = suggest_bump(&mrs, &commits);
+
+        assert_eq!(suggestion.bump, Bump::Major);
+        assert!(suggestion.because_of.is_empty());
+        assert_eq!(suggestion.commits_because_of.len(), 1);
+    }
+
+    #[test]
+    fn no_mrs_is_a_patch() {
+        assert_eq!(suggest_bump(&[], &[]).bump, Bump::Patch);
+    }
+}
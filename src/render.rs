@@ -0,0 +1,659 @@
+use crate::conventional::CommitNote;
+use crate::err::CliError;
+use crate::forge::MergeRequest;
+use crate::sections::{group_by_section, Section};
+use crate::ReleaseNotes;
+use handlebars::Handlebars;
+use serde::Serialize;
+use std::fmt::Write;
+
+/// Bumped whenever a field in the json output is removed or changes meaning. Adding fields
+/// doesn't bump it.
+pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    /// Progress and merge requests for reading in a terminal.
+    Plain,
+    /// Ready to paste into a GitLab release or wiki page.
+    Markdown,
+    /// For other tools to consume, see [`JsonNotes`].
+    Json,
+    /// Through a user supplied handlebars template, see [`TemplateNotes`].
+    Template,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Result<Format, CliError<'static>> {
+        match name {
+            "plain" => Ok(Format::Plain),
+            "markdown" => Ok(Format::Markdown),
+            "json" => Ok(Format::Json),
+            "template" => Ok(Format::Template),
+            _ => Err(CliError::String(format!("Unknown format: {}", name))),
+        }
+    }
+}
+
+/// Builds links to merge requests and pull requests on their forge's pages.
+pub struct MrLinks {
+    /// The forge's root and the project's page, `None` when there's no forge to link to.
+    urls: Option<(String, String)>,
+    change_path: String,
+}
+
+impl MrLinks {
+    /// `web_url` is the release's project page, for merge requests that don't name another
+    /// project.
+    pub fn new(base_url: &str, web_url: &str) -> MrLinks {
+        MrLinks {
+            urls: Some((
+                base_url.trim_end_matches('/').to_string(),
+                web_url.trim_end_matches('/').to_string(),
+            )),
+            change_path: "-/merge_requests".to_string(),
+        }
+    }
+
+    /// For `--offline`, where merge requests are only known by their references.
+    pub fn unlinked() -> MrLinks {
+        MrLinks {
+            urls: None,
+            change_path: String::new(),
+        }
+    }
+
+    /// Where the forge keeps its merge requests under a project's page, if not at GitLab's
+    /// `-/merge_requests`.
+    pub fn with_change_path(mut self, change_path: &str) -> MrLinks {
+        self.change_path = change_path.to_string();
+        self
+    }
+
+    pub fn url(&self, mr: &MergeRequest) -> Option<String> {
+        let (base_url, web_url) = self.urls.as_ref()?;
+
+        Some(match &mr.project {
+            Some(path) => format!("{}/{}/{}/{}", base_url, path, self.change_path, mr.iid),
+            None => format!("{}/{}/{}", web_url, self.change_path, mr.iid),
+        })
+    }
+}
+
+/// A `## title` heading, then a bullet per merge request and conventional commit, under a `###`
+/// heading per section when there are any.
+pub fn markdown(
+    title: &str,
+    notes: &ReleaseNotes,
+    sections: &[Section],
+    links: &MrLinks,
+) -> String {
+    let mut out = format!("## {}\n", title);
+
+    if sections.is_empty() {
+        out.push('\n');
+        for mr in &notes.mrs {
+            markdown_mr(&mut out, mr, links);
+        }
+        for note in &notes.commit_notes {
+            markdown_commit(&mut out, note);
+        }
+    } else {
+        for section in group_by_section(&notes.mrs, &notes.commit_notes, sections) {
+            write!(out, "\n### {}\n\n", section.title).unwrap();
+            for mr in section.mrs {
+                markdown_mr(&mut out, mr, links);
+            }
+            for note in section.commits {
+                markdown_commit(&mut out, note);
+            }
+        }
+    }
+
+    out
+}
+
+/// The json output, version [`JSON_SCHEMA_VERSION`].
+#[derive(Debug, Serialize)]
+pub struct JsonNotes<'a> {
+    pub schema_version: u32,
+    /// The tag or ref the release is measured from (exclusive).
+    pub base: &'a str,
+    /// The ref the release is measured up to (inclusive).
+    pub up_to: &'a str,
+    /// Full shas, newest first.
+    pub commits: Vec<String>,
+    /// Full shas of commits that couldn't be tied to a merge request.
+    pub unmatched_commits: Vec<String>,
+    pub merge_requests: Vec<JsonMr<'a>>,
+    /// Commits without a merge request that were read as conventional commits.
+    pub conventional_commits: Vec<JsonCommit<'a>>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct JsonMr<'a> {
+    pub iid: u64,
+    /// The `group/project` path for merge requests from another project, otherwise null.
+    pub project: Option<&'a str>,
+    /// e.g. `!12` or `group/other!12`.
+    pub reference: String,
+    pub title: &'a str,
+    pub description: &'a str,
+    /// Null with `--offline`, as is `web_url`.
+    pub author: Option<JsonAuthor<'a>>,
+    pub labels: &'a [String],
+    /// The milestone's title.
+    pub milestone: Option<&'a str>,
+    /// Null with `--offline`.
+    pub target_branch: Option<&'a str>,
+    pub draft: bool,
+    pub web_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct JsonCommit<'a> {
+    pub sha: String,
+    #[serde(rename = "type")]
+    pub commit_type: &'a str,
+    pub scope: Option<&'a str>,
+    pub breaking: bool,
+    pub description: &'a str,
+}
+
+#[derive(Debug, Serialize, PartialEq, Clone, Copy)]
+pub struct JsonAuthor<'a> {
+    pub username: &'a str,
+    pub name: &'a str,
+}
+
+impl JsonAuthor<'static> {
+    /// Offline merge requests have no author, but templates can still refer to one's fields.
+    const UNKNOWN: JsonAuthor<'static> = JsonAuthor {
+        username: "",
+        name: "",
+    };
+}
+
+impl<'a> JsonNotes<'a> {
+    pub fn new(notes: &'a ReleaseNotes, links: &MrLinks) -> JsonNotes<'a> {
+        JsonNotes {
+            schema_version: JSON_SCHEMA_VERSION,
+            base: notes.base.as_str(),
+            up_to: notes.up_to.as_str(),
+            commits: notes.commits.iter().map(|oid| oid.to_string()).collect(),
+            unmatched_commits: notes
+                .unmatched_commits
+                .iter()
+                .map(|oid| oid.to_string())
+                .collect(),
+            merge_requests: notes.mrs.iter().map(|mr| JsonMr::new(mr, links)).collect(),
+            conventional_commits: notes.commit_notes.iter().map(JsonCommit::new).collect(),
+        }
+    }
+}
+
+impl<'a> JsonCommit<'a> {
+    fn new(note: &'a CommitNote) -> JsonCommit<'a> {
+        JsonCommit {
+            sha: note.id.to_string(),
+            commit_type: note.commit.commit_type.as_str(),
+            scope: note.commit.scope.as_deref(),
+            breaking: note.commit.breaking,
+            description: note.commit.description.as_str(),
+        }
+    }
+}
+
+impl<'a> JsonMr<'a> {
+    fn new(mr: &'a MergeRequest, links: &MrLinks) -> JsonMr<'a> {
+        JsonMr {
+            iid: mr.iid,
+            project: mr.project.as_deref(),
+            reference: mr.reference(),
+            title: mr.title.as_str(),
+            description: mr.description.as_str(),
+            author: mr.author.as_ref().map(|author| JsonAuthor {
+                username: author.username.as_str(),
+                name: author.name.as_str(),
+            }),
+            labels: mr.labels.as_slice(),
+            milestone: mr
+                .milestone
+                .as_ref()
+                .map(|milestone| milestone.title.as_str()),
+            target_branch: mr.target_branch.as_deref(),
+            draft: mr.draft,
+            web_url: links.url(mr),
+        }
+    }
+}
+
+/// What templates can refer to. Merge requests and conventional commits have the same fields as
+/// in the json output, see [`TemplateMr`] for the one difference.
+#[derive(Debug, Serialize)]
+pub struct TemplateNotes<'a> {
+    pub base: &'a str,
+    pub up_to: &'a str,
+    /// The day the notes were made, as `YYYY-MM-DD`.
+    pub date: &'a str,
+    /// Only the sections with something in them, `Other` last.
+    pub sections: Vec<TemplateSection<'a>>,
+    pub merge_requests: Vec<TemplateMr<'a>>,
+    pub conventional_commits: Vec<JsonCommit<'a>>,
+    /// Everyone with a merge request in the release, in the order they first turn up. Empty
+    /// with `--offline`.
+    pub authors: Vec<JsonAuthor<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateSection<'a> {
+    pub title: &'a str,
+    pub merge_requests: Vec<TemplateMr<'a>>,
+    /// Sorted by scope.
+    pub conventional_commits: Vec<JsonCommit<'a>>,
+}
+
+/// A [`JsonMr`] whose author is never null, so strict templates can refer to the author's fields
+/// with `--offline`.
+#[derive(Debug, Serialize)]
+pub struct TemplateMr<'a> {
+    pub iid: u64,
+    pub project: Option<&'a str>,
+    pub reference: String,
+    pub title: &'a str,
+    pub description: &'a str,
+    /// With empty fields with `--offline`.
+    pub author: JsonAuthor<'a>,
+    pub labels: &'a [String],
+    pub milestone: Option<&'a str>,
+    pub target_branch: Option<&'a str>,
+    pub draft: bool,
+    pub web_url: Option<String>,
+}
+
+impl<'a> From<JsonMr<'a>> for TemplateMr<'a> {
+    fn from(mr: JsonMr<'a>) -> TemplateMr<'a> {
+        TemplateMr {
+            iid: mr.iid,
+            project: mr.project,
+            reference: mr.reference,
+            title: mr.title,
+            description: mr.description,
+            author: mr.author.unwrap_or(JsonAuthor::UNKNOWN),
+            labels: mr.labels,
+            milestone: mr.milestone,
+            target_branch: mr.target_branch,
+            draft: mr.draft,
+            web_url: mr.web_url,
+        }
+    }
+}
+
+impl<'a> TemplateNotes<'a> {
+    pub fn new(
+        notes: &'a ReleaseNotes,
+        sections: &'a [Section],
+        links: &MrLinks,
+        date: &'a str,
+    ) -> TemplateNotes<'a> {
+        let merge_requests: Vec<JsonMr> =
+            notes.mrs.iter().map(|mr| JsonMr::new(mr, links)).collect();
+
+        let mut authors: Vec<JsonAuthor> = Vec::new();
+        for author in merge_requests.iter().filter_map(|mr| mr.author) {
+            if !authors.contains(&author) {
+                authors.push(author);
+            }
+        }
+
+        TemplateNotes {
+            base: notes.base.as_str(),
+            up_to: notes.up_to.as_str(),
+            date,
+            sections: group_by_section(&notes.mrs, &notes.commit_notes, sections)
+                .into_iter()
+                .map(|section| TemplateSection {
+                    title: section.title,
+                    merge_requests: section
+                        .mrs
+                        .into_iter()
+                        .map(|mr| JsonMr::new(mr, links).into())
+                        .collect(),
+                    conventional_commits: section
+                        .commits
+                        .into_iter()
+                        .map(JsonCommit::new)
+                        .collect(),
+                })
+                .collect(),
+            merge_requests: merge_requests.into_iter().map(TemplateMr::from).collect(),
+            conventional_commits: notes.commit_notes.iter().map(JsonCommit::new).collect(),
+            authors,
+        }
+    }
+}
+
+/// Renders `template` as a handlebars template, without html escaping. Referring to anything
+/// that isn't in [`TemplateNotes`] is an error, rather than quietly rendering nothing.
+pub fn template(template: &str, notes: &TemplateNotes) -> Result<String, CliError<'static>> {
+    let mut handlebars = Handlebars::new();
+    handlebars.set_strict_mode(true);
+    handlebars.register_escape_fn(handlebars::no_escape);
+
+    handlebars
+        .render_template(template, notes)
+        .map_err(|e| CliError::Template("Could not render template", Box::new(e)))
+}
+
+pub fn json(notes: &ReleaseNotes, links: &MrLinks) -> Result<String, CliError<'static>> {
+    serde_json::to_string_pretty(&JsonNotes::new(notes, links))
+        .map_err(|e| CliError::Json("Could not serialize release notes", e))
+}
+
+fn markdown_mr(out: &mut String, mr: &MergeRequest, links: &MrLinks) {
+    match links.url(mr) {
+        Some(url) => write!(out, "- {} ([{}]({}))", mr.title, mr.reference(), url),
+        None => write!(out, "- {} ({})", mr.title, mr.reference()),
+    }
+    .unwrap();
+    match &mr.author {
+        Some(author) => writeln!(out, " by @{}", author.username),
+        None => writeln!(out),
+    }
+    .unwrap();
+}
+
+fn markdown_commit(out: &mut String, note: &CommitNote) {
+    out.push_str("- ");
+    if note.commit.breaking {
+        out.push_str("**Breaking:** ");
+    }
+    if let Some(scope) = &note.commit.scope {
+        write!(out, "**{}:** ", scope).unwrap();
+    }
+    writeln!(
+        out,
+        "{} ({:.8})",
+        note.commit.description,
+        note.id.to_string()
+    )
+    .unwrap();
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conventional::{parse, CommitNote};
+    use crate::forge::MergeRequest;
+    use crate::mr_helpers::mr_helpers::mr;
+    use crate::render::{json, markdown, template, Format, MrLinks, TemplateNotes};
+    use crate::sections::Section;
+    use crate::ReleaseNotes;
+    use git2::Oid;
+
+    fn links() -> MrLinks {
+        MrLinks::new(
+            "https://gitlab.example.com/",
+            "https://gitlab.example.com/group/project",
+        )
+    }
+
+    fn note(message: &str) -> CommitNote {
+        CommitNote {
+            id: Oid::from_str("8f9e2a1b3c4d5e6f708192a3b4c5d6e7f8091a2b").unwrap(),
+            commit: parse(message).unwrap(),
+        }
+    }
+
+    fn notes(mrs: Vec<MergeRequest>) -> ReleaseNotes {
+        ReleaseNotes {
+            base: "v1.0.0".to_string(),
+            up_to: "HEAD".to_string(),
+            commits: Vec::new(),
+            mr_ids: Vec::new(),
+            unmatched_commits: Vec::new(),
+            commit_notes: Vec::new(),
+            mrs,
+            filtered_out: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn links_own_and_cross_project_mrs() {
+        assert_eq!(
+            links().url(&mr(12).build()).unwrap(),
+            "https://gitlab.example.com/group/project/-/merge_requests/12"
+        );
+        assert_eq!(
+            links().url(&mr(3).project("group/other").build()).unwrap(),
+            "https://gitlab.example.com/group/other/-/merge_requests/3"
+        );
+    }
+
+    #[test]
+    fn links_pull_requests() {
+        let links = MrLinks::new("https://github.com", "https://github.com/owner/repo")
+            .with_change_path("pull");
+        let pr = mr(12).pull_request().build();
+
+        assert_eq!(
+            links.url(&pr).unwrap(),
+            "https://github.com/owner/repo/pull/12"
+        );
+
+        let pr = mr(12).pull_request().project("owner/other").build();
+        assert_eq!(
+            links.url(&pr).unwrap(),
+            "https://github.com/owner/other/pull/12"
+        );
+    }
+
+    #[test]
+    fn markdown_without_sections_is_a_flat_list() {
+        let notes = notes(vec![mr(12).build(), mr(3).project("group/other").build()]);
+
+        assert_eq!(
+            markdown("v1.1.0", &notes, &[], &links()),
+            "## v1.1.0
+
+- MR 12 ([!12](https://gitlab.example.com/group/project/-/merge_requests/12)) by @alice
+- MR 3 ([group/other!3](https://gitlab.example.com/group/other/-/merge_requests/3)) by @alice
+"
+        );
+    }
+
+    #[test]
+    fn markdown_offline_has_bare_references() {
+        let offline = mr(12).no_author().build();
+
+        assert_eq!(
+            markdown("v1.1.0", &notes(vec![offline]), &[], &MrLinks::unlinked()),
+            "## v1.1.0\n\n- MR 12 (!12)\n"
+        );
+    }
+
+    #[test]
+    fn markdown_with_sections_has_a_heading_each() {
+        let notes = notes(vec![mr(1).labels(&["bug"]).build(), mr(2).build()]);
+        let sections = vec![Section {
+            title: "Bug fixes".to_string(),
+            labels: vec!["bug".to_string()],
+            types: vec!["fix".to_string()],
+        }];
+
+        assert_eq!(
+            markdown("v1.1.0", &notes, &sections, &links()),
+            "## v1.1.0
+
+### Bug fixes
+
+- MR 1 ([!1](https://gitlab.example.com/group/project/-/merge_requests/1)) by @alice
+
+### Other
+
+- MR 2 ([!2](https://gitlab.example.com/group/project/-/merge_requests/2)) by @alice
+"
+        );
+    }
+
+    #[test]
+    fn markdown_mixes_mrs_and_conventional_commits() {
+        let mut notes = notes(vec![mr(1).labels(&["bug"]).build()]);
+        notes.commit_notes = vec![
+            note("fix(parser)!: reject empty tags"),
+            note("chore: bump deps"),
+        ];
+        let sections = vec![Section {
+            title: "Bug fixes".to_string(),
+            labels: vec!["bug".to_string()],
+            types: vec!["fix".to_string()],
+        }];
+
+        assert_eq!(
+            markdown("v1.1.0", &notes, &sections, &links()),
+            "## v1.1.0
+
+### Bug fixes
+
+- MR 1 ([!1](https://gitlab.example.com/group/project/-/merge_requests/1)) by @alice
+- **Breaking:** **parser:** reject empty tags (8f9e2a1b)
+
+### Other
+
+- bump deps (8f9e2a1b)
+"
+        );
+    }
+
+    #[test]
+    fn json_has_versioned_schema() {
+        let mr = mr(12)
+            .labels(&["bug"])
+            .milestone("1.1")
+            .target_branch("main")
+            .build();
+        let mut notes = notes(vec![mr]);
+        let commit = Oid::from_str("8f9e2a1b3c4d5e6f708192a3b4c5d6e7f8091a2b").unwrap();
+        notes.commits = vec![commit];
+        notes.commit_notes = vec![note("feat(ui): add dark mode")];
+
+        let value: serde_json::Value =
+            serde_json::from_str(json(&notes, &links()).unwrap().as_str()).unwrap();
+
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "schema_version": 1,
+                "base": "v1.0.0",
+                "up_to": "HEAD",
+                "commits": ["8f9e2a1b3c4d5e6f708192a3b4c5d6e7f8091a2b"],
+                "unmatched_commits": [],
+                "conventional_commits": [{
+                    "sha": "8f9e2a1b3c4d5e6f708192a3b4c5d6e7f8091a2b",
+                    "type": "feat",
+                    "scope": "ui",
+                    "breaking": false,
+                    "description": "add dark mode"
+                }],
+                "merge_requests": [{
+                    "iid": 12,
+                    "project": null,
+                    "reference": "!12",
+                    "title": "MR 12",
+                    "description": "",
+                    "author": {"username": "alice", "name": "Alice"},
+                    "labels": ["bug"],
+                    "milestone": "1.1",
+                    "target_branch": "main",
+                    "draft": false,
+                    "web_url": "https://gitlab.example.com/group/project/-/merge_requests/12"
+                }]
+            })
+        );
+    }
+
+    #[test]
+    fn json_offline_author_is_null() {
+        let notes = notes(vec![mr(12).no_author().build()]);
+
+        let value: serde_json::Value =
+            serde_json::from_str(json(&notes, &MrLinks::unlinked()).unwrap().as_str()).unwrap();
+
+        assert_eq!(value["merge_requests"][0]["author"], serde_json::Value::Null);
+        assert_eq!(value["merge_requests"][0]["web_url"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn template_can_use_sections_and_authors() {
+        let bob = mr(2).author("bob").build();
+        let notes = notes(vec![mr(1).labels(&["bug"]).build(), bob, mr(3).build()]);
+        let sections = vec![Section {
+            title: "Bug fixes".to_string(),
+            labels: vec!["bug".to_string()],
+            types: vec!["fix".to_string()],
+        }];
+        let context = TemplateNotes::new(&notes, &sections, &links(), "2024-03-01");
+
+        let rendered = template(
+            "# Since {{base}} ({{date}})
+{{#each sections}}## {{title}}
+{{#each merge_requests}}* {{title}} <{{web_url}}>
+{{/each}}{{/each}}Thanks {{#each authors}}{{username}} {{/each}}",
+            &context,
+        )
+        .unwrap();
+
+        assert_eq!(
+            rendered,
+            "# Since v1.0.0 (2024-03-01)
+## Bug fixes
+* MR 1 <https://gitlab.example.com/group/project/-/merge_requests/1>
+## Other
+* MR 2 <https://gitlab.example.com/group/project/-/merge_requests/2>
+* MR 3 <https://gitlab.example.com/group/project/-/merge_requests/3>
+Thanks alice bob "
+        );
+    }
+
+    #[test]
+    fn template_can_refer_to_authors_offline() {
+        let offline = mr(12).no_author().build();
+        let notes = notes(vec![offline]);
+        let context = TemplateNotes::new(&notes, &[], &MrLinks::unlinked(), "2024-03-01");
+
+        let rendered = template(
+            "{{#each merge_requests}}{{reference}} by @{{author.username}} {{web_url}}{{/each}}\
+             {{#each authors}} thanks {{username}}{{/each}}",
+            &context,
+        )
+        .unwrap();
+
+        assert_eq!(rendered, "!12 by @ ");
+    }
+
+    #[test]
+    fn template_does_not_escape_html() {
+        let notes = notes(vec![mr(1).title("Use <T> & friends").build()]);
+        let context = TemplateNotes::new(&notes, &[], &links(), "2024-03-01");
+
+        assert_eq!(
+            template("{{merge_requests.0.title}}", &context).unwrap(),
+            "Use <T> & friends"
+        );
+    }
+
+    #[test]
+    fn template_with_unknown_field_is_an_error() {
+        let notes = notes(vec![]);
+        let context = TemplateNotes::new(&notes, &[], &links(), "2024-03-01");
+
+        assert!(template("{{relase_date}}", &context).is_err());
+    }
+
+    #[test]
+    fn unknown_format_name_is_an_error() {
+        assert_eq!(
+            Format::from_name("html").unwrap_err().to_string(),
+            "Unknown format: html"
+        );
+    }
+}
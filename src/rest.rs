@@ -0,0 +1,458 @@
+use crate::err::CliError;
+use crate::forge::{ApiRequest, ChangeKind, Forge, MergeRequest, Milestone, User};
+use crate::render::MrLinks;
+use reqwest::header::{AUTHORIZATION, CONTENT_TYPE, USER_AGENT};
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
+
+/// Where GitHub's api and Gitea's, which copies it, differ.
+pub trait Api {
+    /// e.g. `GitHub`, for error messages.
+    const NAME: &'static str;
+    /// What comes between `base_url` and `/repos`, e.g. `/api/v1`.
+    const API_PATH: &'static str;
+    /// Where pull requests are on the web, `pull` in `https://github.com/owner/repo/pull/12`.
+    const CHANGE_PATH: &'static str;
+
+    /// Adds anything the api needs on top of the token.
+    fn headers(request: RequestBuilder) -> RequestBuilder {
+        request
+    }
+
+    /// The pull requests the forge knows contain `sha`.
+    fn get_mrs_for_commit(
+        repo: &Repo<Self>,
+        sha: &str,
+    ) -> Result<Vec<MergeRequest>, CliError<'static>>
+    where
+        Self: Sized;
+}
+
+/// An `owner/repo` repository behind a GitHub style api, see [`Api`].
+pub struct Repo<A> {
+    base_url: String,
+    /// `owner/repo`
+    path: String,
+    api_token: String,
+    client: reqwest::Client,
+    api: PhantomData<A>,
+}
+
+#[derive(Debug, Serialize)]
+struct ReleaseBody<'a> {
+    tag_name: &'a str,
+    name: &'a str,
+    body: &'a str,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReleaseInfo {
+    id: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RepoInfo {
+    html_url: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PullRequest {
+    number: u64,
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    user: ApiUser,
+    #[serde(default)]
+    labels: Vec<Label>,
+    milestone: Option<Milestone>,
+    base: Branch,
+    #[serde(default)]
+    draft: bool,
+    merged_at: Option<String>,
+    merge_commit_sha: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Branch {
+    #[serde(rename = "ref")]
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiUser {
+    id: u64,
+    login: String,
+    /// Only Gitea has it, GitHub only has display names on the user's own endpoint.
+    #[serde(default)]
+    full_name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Label {
+    name: String,
+}
+
+impl From<PullRequest> for MergeRequest {
+    fn from(pr: PullRequest) -> MergeRequest {
+        let name = if pr.user.full_name.is_empty() {
+            pr.user.login.clone()
+        } else {
+            pr.user.full_name
+        };
+
+        MergeRequest {
+            iid: pr.number,
+            title: pr.title,
+            description: pr.body.unwrap_or_default(),
+            author: Some(User {
+                id: pr.user.id,
+                name,
+                username: pr.user.login,
+            }),
+            labels: pr.labels.into_iter().map(|l| l.name).collect(),
+            milestone: pr.milestone,
+            target_branch: Some(pr.base.name),
+            draft: pr.draft,
+            merged: pr.merged_at.is_some(),
+            merge_commit_sha: pr.merge_commit_sha,
+            squash_commit_sha: None,
+            project: None,
+            kind: ChangeKind::PullRequest,
+        }
+    }
+}
+
+impl<A: Api> Repo<A> {
+    pub fn new(
+        base_url: String,
+        path: &str,
+        api_token: String,
+    ) -> Result<Repo<A>, CliError<'static>> {
+        if path.split('/').count() != 2 {
+            return Err(CliError::String(format!(
+                "{} repositories look like owner/repo, not {}",
+                A::NAME,
+                path
+            )));
+        }
+
+        Ok(Repo {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            path: path.to_string(),
+            api_token,
+            client: Client::new(),
+            api: PhantomData,
+        })
+    }
+
+    pub(crate) fn repo_url(&self, path: &str) -> String {
+        format!("{}{}/repos/{}", self.base_url, A::API_PATH, path)
+    }
+
+    /// This repository's api url.
+    pub(crate) fn own_url(&self) -> String {
+        self.repo_url(self.path.as_str())
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        A::headers(
+            self.client
+                .request(method, url)
+                .header(AUTHORIZATION, format!("token {}", self.api_token))
+                .header(USER_AGENT, "shippy"),
+        )
+    }
+
+    pub(crate) fn get(&self, url: &str) -> Result<Response, CliError<'static>> {
+        self.request(Method::GET, url)
+            .send()
+            .map_err(|e| CliError::Http(format!("Error getting {}", url), e))
+    }
+
+    pub(crate) fn get_json<T: DeserializeOwned>(&self, url: &str) -> Result<T, CliError<'static>> {
+        let mut response = self.get(url)?;
+
+        if !response.status().is_success() {
+            return Err(CliError::String(format!(
+                "Got {} from {}",
+                response.status(),
+                url
+            )));
+        }
+
+        response.json::<T>().map_err(|e| {
+            let message = format!("Could not deserialize json from {}:\n {:#?}", url, response);
+            CliError::Http(message, e)
+        })
+    }
+
+    /// The repository's page, e.g. `https://github.com/owner/repo`.
+    pub fn get_web_url(&self) -> Result<String, CliError<'static>> {
+        self.get_json::<RepoInfo>(self.own_url().as_str())
+            .map(|info| info.html_url)
+    }
+}
+
+impl<A: Api> Forge for Repo<A> {
+    /// Neither api can list pull requests by number, so this asks for each in turn. Numbers
+    /// that aren't pull requests, e.g. issues or deleted pull requests, are left out, as GitLab
+    /// leaves out iids it doesn't have.
+    fn get_mrs(
+        &self,
+        project: Option<&str>,
+        ids: Vec<u64>,
+    ) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        let repo_url = self.repo_url(project.unwrap_or(self.path.as_str()));
+
+        let mut mrs = Vec::new();
+        for id in ids {
+            let url = format!("{}/pulls/{}", repo_url, id);
+            let mut response = self.get(url.as_str())?;
+            let pr = match response.status() {
+                StatusCode::NOT_FOUND => continue,
+                status if status.is_success() => response.json::<PullRequest>().map_err(|e| {
+                    CliError::Http(format!("Could not deserialize json from {}", url), e)
+                })?,
+                status => {
+                    return Err(CliError::String(format!("Got {} from {}", status, url)))
+                }
+            };
+
+            let mut mr = MergeRequest::from(pr);
+            mr.project = project.map(str::to_string);
+            mrs.push(mr);
+        }
+
+        Ok(mrs)
+    }
+
+    fn get_mrs_for_commit(&self, sha: &str) -> Result<Vec<MergeRequest>, CliError<'static>> {
+        A::get_mrs_for_commit(self, sha)
+    }
+
+    fn links(&self) -> Result<MrLinks, CliError<'static>> {
+        let web_url = self.get_web_url()?;
+        let host = web_url
+            .trim_end_matches('/')
+            .trim_end_matches(self.path.as_str())
+            .trim_end_matches('/');
+
+        Ok(MrLinks::new(host, web_url.as_str()).with_change_path(A::CHANGE_PATH))
+    }
+
+    fn release_request(
+        &self,
+        tag: &str,
+        description: &str,
+    ) -> Result<ApiRequest, CliError<'static>> {
+        let releases_url = format!("{}/releases", self.own_url());
+        let tag_url = format!("{}/tags/{}", releases_url, tag.replace("/", "%2F"));
+
+        let mut response = self.get(tag_url.as_str())?;
+        let (method, url) = match response.status() {
+            StatusCode::NOT_FOUND => (Method::POST, releases_url),
+            status if status.is_success() => {
+                let release = response.json::<ReleaseInfo>().map_err(|e| {
+                    CliError::Http(format!("Could not deserialize json from {}", tag_url), e)
+                })?;
+                (Method::PATCH, format!("{}/{}", releases_url, release.id))
+            }
+            status => {
+                return Err(CliError::String(format!(
+                    "Could not check for release {}, got {} from {}",
+                    tag, status, tag_url
+                )))
+            }
+        };
+
+        let body = serde_json::to_string_pretty(&ReleaseBody {
+            tag_name: tag,
+            name: tag,
+            body: description,
+        })
+        .map_err(|e| CliError::Json("Could not serialize release", e))?;
+
+        Ok(ApiRequest { method, url, body })
+    }
+
+    fn send(&self, request: &ApiRequest) -> Result<(), CliError<'static>> {
+        let mut response = self
+            .request(request.method.clone(), request.url.as_str())
+            .header(CONTENT_TYPE, "application/json")
+            .body(request.body.clone())
+            .send()
+            .map_err(|e| {
+                CliError::Http(
+                    format!("Error sending {} {}", request.method, request.url),
+                    e,
+                )
+            })?;
+
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(CliError::String(format!(
+                "{} {} failed with {}:\n{}",
+                request.method,
+                request.url,
+                response.status(),
+                response.text().unwrap_or_default()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::forge::{ApiRequest, ChangeKind, Forge};
+    use crate::github::{GitHub, Repo};
+    use crate::http_helpers::http_helpers::{mock_server, MockRequest, MockResponse};
+    use crate::mr_helpers::mr_helpers::mr;
+    use crate::rest;
+    use reqwest::Method;
+
+    fn repo(base_url: &str) -> Repo {
+        Repo::new(base_url.to_string(), "owner/repo", "token".to_string()).unwrap()
+    }
+
+    fn pr_json(number: u64) -> String {
+        mr(number)
+            .labels(&["bug"])
+            .milestone("1.0")
+            .target_branch("main")
+            .draft()
+            .pull_request()
+            .json()
+    }
+
+    #[test]
+    fn rejects_paths_that_are_not_owner_repo() {
+        let new = |path| rest::Repo::<GitHub>::new("x".to_string(), path, String::new());
+
+        assert!(new("repo").is_err());
+        assert!(new("group/sub/repo").is_err());
+    }
+
+    #[test]
+    fn get_mrs_fetches_each_pull_request() {
+        let base_url = mock_server(|req: &MockRequest| match req.path.as_str() {
+            "/repos/owner/repo/pulls/1" => MockResponse::json(pr_json(1).as_str()),
+            "/repos/owner/other/pulls/2" => MockResponse::json(pr_json(2).as_str()),
+            _ => MockResponse::status(404),
+        });
+        let repo = repo(base_url.as_str());
+
+        let own = repo.get_mrs(None, vec![1]).unwrap();
+        assert_eq!(own[0].iid, 1);
+        assert_eq!(own[0].kind, ChangeKind::PullRequest);
+        assert_eq!(own[0].author.as_ref().unwrap().username, "alice");
+        assert_eq!(own[0].labels, vec!["bug"]);
+        assert_eq!(own[0].milestone.as_ref().unwrap().title, "1.0");
+        assert_eq!(own[0].target_branch.as_deref(), Some("main"));
+        assert!(own[0].draft);
+        assert_eq!(own[0].reference(), "#1");
+
+        let other = repo.get_mrs(Some("owner/other"), vec![2]).unwrap();
+        assert_eq!(other[0].reference(), "owner/other#2");
+    }
+
+    #[test]
+    fn get_mrs_leaves_out_numbers_that_are_not_pull_requests() {
+        let base_url = mock_server(|req: &MockRequest| match req.path.as_str() {
+            "/repos/owner/repo/pulls/1" => MockResponse::json(pr_json(1).as_str()),
+            "/repos/owner/repo/pulls/3" => MockResponse::json(pr_json(3).as_str()),
+            "/repos/owner/repo/pulls/4" => MockResponse::status(500),
+            _ => MockResponse::status(404),
+        });
+        let repo = repo(base_url.as_str());
+
+        let found = repo.get_mrs(None, vec![1, 2, 3]).unwrap();
+        assert_eq!(
+            found.iter().map(|mr| mr.iid).collect::<Vec<_>>(),
+            vec![1, 3]
+        );
+        assert!(repo.get_mrs(None, vec![4]).is_err());
+    }
+
+    #[test]
+    fn links_point_at_pull_pages() {
+        let base_url = mock_server(|req: &MockRequest| match req.path.as_str() {
+            "/repos/owner/repo" => {
+                MockResponse::json(r#"{"html_url": "https://github.com/owner/repo"}"#)
+            }
+            _ => MockResponse::json(pr_json(5).as_str()),
+        });
+        let repo = repo(base_url.as_str());
+
+        let links = repo.links().unwrap();
+        let own = repo.get_mrs(None, vec![5]).unwrap();
+        let other = repo.get_mrs(Some("owner/other"), vec![5]).unwrap();
+
+        assert_eq!(
+            links.url(&own[0]).unwrap(),
+            "https://github.com/owner/repo/pull/5"
+        );
+        assert_eq!(
+            links.url(&other[0]).unwrap(),
+            "https://github.com/owner/other/pull/5"
+        );
+    }
+
+    #[test]
+    fn release_request_creates_missing_release() {
+        let base_url = mock_server(|req: &MockRequest| {
+            assert_eq!(req.path, "/repos/owner/repo/releases/tags/release%2F1.2");
+            MockResponse::status(404)
+        });
+
+        let request = repo(base_url.as_str())
+            .release_request("release/1.2", "Notes")
+            .unwrap();
+
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(
+            request.url,
+            format!("{}/repos/owner/repo/releases", base_url)
+        );
+        let body: serde_json::Value = serde_json::from_str(request.body.as_str()).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({"tag_name": "release/1.2", "name": "release/1.2", "body": "Notes"})
+        );
+    }
+
+    #[test]
+    fn release_request_updates_existing_release() {
+        let base_url = mock_server(|_: &MockRequest| {
+            MockResponse::json(r#"{"id": 42, "tag_name": "v1.2.0"}"#)
+        });
+
+        let request = repo(base_url.as_str())
+            .release_request("v1.2.0", "Notes")
+            .unwrap();
+
+        assert_eq!(request.method, Method::PATCH);
+        assert_eq!(
+            request.url,
+            format!("{}/repos/owner/repo/releases/42", base_url)
+        );
+    }
+
+    #[test]
+    fn sends_the_token() {
+        let base_url = mock_server(|req: &MockRequest| {
+            assert_eq!(req.header("Authorization"), Some("token secret"));
+            MockResponse::status(201)
+        });
+        let repo = Repo::new(base_url.clone(), "owner/repo", "secret".to_string()).unwrap();
+
+        repo.send(&ApiRequest {
+            method: Method::POST,
+            url: format!("{}/repos/owner/repo/releases", base_url),
+            body: "{}".to_string(),
+        })
+        .unwrap();
+    }
+}
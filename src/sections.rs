@@ -0,0 +1,205 @@
+use crate::conventional::CommitNote;
+use crate::forge::MergeRequest;
+use serde::Deserialize;
+
+/// Where merge requests that aren't in any configured section end up.
+pub const OTHER_SECTION: &str = "Other";
+
+/// A heading in the release notes, with the merge request labels and conventional commit types
+/// that go under it.
+#[derive(Debug, PartialEq, Deserialize)]
+pub struct Section {
+    pub title: String,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub types: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SectionNotes<'a> {
+    pub title: &'a str,
+    pub mrs: Vec<&'a MergeRequest>,
+    /// Sorted by scope, commits without one first.
+    pub commits: Vec<&'a CommitNote>,
+}
+
+/// Used for conventional commits when no sections are configured.
+pub fn default_sections() -> Vec<Section> {
+    let section = |title: &str, labels: &[&str], types: &[&str]| Section {
+        title: title.to_string(),
+        labels: labels.iter().map(|l| l.to_string()).collect(),
+        types: types.iter().map(|t| t.to_string()).collect(),
+    };
+
+    vec![
+        section("Features", &["feature"], &["feat"]),
+        section("Bug fixes", &["bug"], &["fix"]),
+        section("Performance", &[], &["perf"]),
+        section("Documentation", &["documentation"], &["docs"]),
+    ]
+}
+
+/// Groups `mrs` and `commits` in the order `sections` are given, with everything else under
+/// [`OTHER_SECTION`] at the end. Each goes in the first section with one of its labels or its
+/// type, and sections with nothing in them are left out.
+pub fn group_by_section<'a>(
+    mrs: &'a [MergeRequest],
+    commits: &'a [CommitNote],
+    sections: &'a [Section],
+) -> Vec<SectionNotes<'a>> {
+    let mut grouped: Vec<SectionNotes> = sections
+        .iter()
+        .map(|s| SectionNotes {
+            title: s.title.as_str(),
+            mrs: Vec::new(),
+            commits: Vec::new(),
+        })
+        .chain(std::iter::once(SectionNotes {
+            title: OTHER_SECTION,
+            mrs: Vec::new(),
+            commits: Vec::new(),
+        }))
+        .collect();
+    let other = sections.len();
+
+    for mr in mrs {
+        let section = sections
+            .iter()
+            .position(|s| s.labels.iter().any(|l| mr.labels.contains(l)));
+
+        grouped[section.unwrap_or(other)].mrs.push(mr);
+    }
+
+    for note in commits {
+        let section = sections
+            .iter()
+            .position(|s| s.types.contains(&note.commit.commit_type));
+
+        grouped[section.unwrap_or(other)].commits.push(note);
+    }
+
+    for section in &mut grouped {
+        section.commits.sort_by_key(|note| &note.commit.scope);
+    }
+    grouped.retain(|s| !s.mrs.is_empty() || !s.commits.is_empty());
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::conventional::{parse, CommitNote};
+    use crate::forge::MergeRequest;
+    use crate::mr_helpers::mr_helpers::mr;
+    use crate::sections::{group_by_section, Section};
+    use git2::Oid;
+
+    fn section(title: &str, labels: &[&str]) -> Section {
+        Section {
+            title: title.to_string(),
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            types: Vec::new(),
+        }
+    }
+
+    fn note(message: &str) -> CommitNote {
+        CommitNote {
+            id: Oid::zero(),
+            commit: parse(message).unwrap(),
+        }
+    }
+
+    fn titles_and_iids(mrs: &[MergeRequest], sections: &[Section]) -> Vec<(String, Vec<u64>)> {
+        group_by_section(mrs, &[], sections)
+            .iter()
+            .map(|s| (s.title.to_string(), s.mrs.iter().map(|mr| mr.iid).collect()))
+            .collect()
+    }
+
+    #[test]
+    fn groups_in_configured_order_with_other_last() {
+        let mrs = vec![
+            mr(1).labels(&["bug"]).build(),
+            mr(2).labels(&["chore"]).build(),
+            mr(3).labels(&["feature"]).build(),
+            mr(4).build(),
+        ];
+        let sections = vec![
+            section("Features", &["feature", "enhancement"]),
+            section("Bug fixes", &["bug"]),
+        ];
+
+        assert_eq!(
+            titles_and_iids(&mrs, &sections),
+            vec![
+                ("Features".to_string(), vec![3]),
+                ("Bug fixes".to_string(), vec![1]),
+                ("Other".to_string(), vec![2, 4]),
+            ]
+        );
+    }
+
+    #[test]
+    fn mr_goes_in_first_matching_section_only() {
+        let mrs = vec![mr(1).labels(&["bug", "breaking"]).build()];
+        let sections = vec![
+            section("Breaking changes", &["breaking"]),
+            section("Bug fixes", &["bug"]),
+        ];
+
+        assert_eq!(
+            titles_and_iids(&mrs, &sections),
+            vec![("Breaking changes".to_string(), vec![1])]
+        );
+    }
+
+    #[test]
+    fn everything_is_other_without_sections() {
+        let mrs = vec![mr(1).labels(&["bug"]).build(), mr(2).build()];
+
+        assert_eq!(
+            titles_and_iids(&mrs, &[]),
+            vec![("Other".to_string(), vec![1, 2])]
+        );
+    }
+
+    #[test]
+    fn commits_group_by_type_and_sort_by_scope() {
+        let mrs = vec![mr(1).labels(&["feature"]).build()];
+        let commits = vec![
+            note("feat(ui): add dark mode"),
+            note("chore: bump deps"),
+            note("feat: add export"),
+            note("feat(api): add filters"),
+        ];
+        let mut features = section("Features", &["feature"]);
+        features.types = vec!["feat".to_string()];
+        let sections = vec![features];
+
+        let grouped: Vec<(&str, Vec<u64>, Vec<&str>)> = group_by_section(&mrs, &commits, &sections)
+            .iter()
+            .map(|s| {
+                (
+                    s.title,
+                    s.mrs.iter().map(|mr| mr.iid).collect(),
+                    s.commits
+                        .iter()
+                        .map(|n| n.commit.description.as_str())
+                        .collect(),
+                )
+            })
+            .collect();
+
+        assert_eq!(
+            grouped,
+            vec![
+                (
+                    "Features",
+                    vec![1],
+                    vec!["add export", "add filters", "add dark mode"]
+                ),
+                ("Other", vec![], vec!["bump deps"]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,406 @@
+use crate::err::CliError;
+use regex::Regex;
+use semver::Version;
+use std::cmp::Ordering;
+use std::fmt;
+
+/// Picks the version out of a tag name, via a `version` named capture group.
+#[derive(Debug)]
+pub struct TagPattern {
+    regex: Regex,
+    description: String,
+}
+
+/// How the version part of a tag is read.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TagScheme {
+    /// A plain integer, e.g. `release-42`.
+    Numeric,
+    /// A semantic version, e.g. `v1.4.2` or `v1.5.0-rc.1`.
+    SemVer { include_pre_releases: bool },
+    /// Dot separated integers, compared left to right, e.g. `2024.03.1`.
+    Dotted,
+}
+
+/// Which part of a version to increment for the next release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Bump {
+    Patch,
+    Minor,
+    Major,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TagVersion {
+    Numeric(u64),
+    SemVer(Version),
+    Dotted(Vec<u64>),
+}
+
+impl TagPattern {
+    pub fn new(pattern: &str) -> Result<TagPattern, CliError<'static>> {
+        let regex = Regex::new(format!("^(?:{})$", pattern).as_str())
+            .map_err(|e| CliError::Regex(format!("Could not parse tag pattern: {}", pattern), e))?;
+
+        if regex
+            .capture_names()
+            .flatten()
+            .all(|name| name != "version")
+        {
+            return Err(CliError::String(format!(
+                "Tag pattern has no (?P<version>...) group: {}",
+                pattern
+            )));
+        }
+
+        Ok(TagPattern {
+            regex,
+            description: format!("pattern: {}", pattern),
+        })
+    }
+
+    pub fn from_prefix(prefix: &str, scheme: &TagScheme) -> Result<TagPattern, CliError<'static>> {
+        if prefix.is_empty() && *scheme == TagScheme::Numeric {
+            return Result::Err(CliError::Str(
+                "Can't find greatest tag with no prefix, would find all tags.",
+            ));
+        }
+
+        let mut pattern =
+            TagPattern::new(format!("{}(?P<version>.+)", regex::escape(prefix)).as_str())?;
+        pattern.description = format!("prefix: {}", prefix);
+        Ok(pattern)
+    }
+
+    pub fn version<'t>(&self, tag: &'t str) -> Option<&'t str> {
+        self.regex
+            .captures(tag)
+            .and_then(|c| c.name("version"))
+            .map(|m| m.as_str())
+    }
+
+    /// Swaps the version in `tag` for `version`, keeping the rest of the tag as is.
+    pub fn with_version(&self, tag: &str, version: &str) -> Option<String> {
+        let m = self.regex.captures(tag)?.name("version")?;
+
+        Some(format!(
+            "{}{}{}",
+            &tag[..m.start()],
+            version,
+            &tag[m.end()..]
+        ))
+    }
+}
+
+impl fmt::Display for TagPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.description.as_str())
+    }
+}
+
+impl Bump {
+    pub fn from_name(name: &str) -> Result<Bump, CliError<'static>> {
+        match name {
+            "major" => Ok(Bump::Major),
+            "minor" => Ok(Bump::Minor),
+            "patch" => Ok(Bump::Patch),
+            _ => Err(CliError::String(format!("Unknown version bump: {}", name))),
+        }
+    }
+}
+
+impl fmt::Display for Bump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Bump::Major => "major",
+            Bump::Minor => "minor",
+            Bump::Patch => "patch",
+        })
+    }
+}
+
+impl TagScheme {
+    pub fn from_name(
+        name: &str,
+        include_pre_releases: bool,
+    ) -> Result<TagScheme, CliError<'static>> {
+        match name {
+            "numeric" => Ok(TagScheme::Numeric),
+            "semver" => Ok(TagScheme::SemVer {
+                include_pre_releases,
+            }),
+            "dotted" => Ok(TagScheme::Dotted),
+            _ => Err(CliError::String(format!("Unknown tag scheme: {}", name))),
+        }
+    }
+
+    /// Returns `None` for anything that isn't a version under this scheme, including
+    /// pre-releases when they've been excluded.
+    pub fn parse(&self, s: &str) -> Option<TagVersion> {
+        match self {
+            TagScheme::Numeric => s.parse::<u64>().ok().map(TagVersion::Numeric),
+            TagScheme::SemVer {
+                include_pre_releases,
+            } => Version::parse(s)
+                .ok()
+                .filter(|v| *include_pre_releases || !v.is_prerelease())
+                .map(TagVersion::SemVer),
+            TagScheme::Dotted => s
+                .split('.')
+                .map(|part| part.parse::<u64>().ok())
+                .collect::<Option<Vec<u64>>>()
+                .map(TagVersion::Dotted),
+        }
+    }
+
+    /// The version after `version`. Numeric versions always go up by one. Dotted versions treat
+    /// their parts as major, minor then patch, with patch being the last part however many there
+    /// are, and keep the width of any zero padded parts. A semver pre-release is released instead
+    /// when it's already at the bump's level. Returns `None` if `version` doesn't parse under
+    /// this scheme, or if the bumped part is already as big as it can be.
+    pub fn bump(&self, version: &str, bump: Bump) -> Option<String> {
+        match self.parse(version)? {
+            TagVersion::Numeric(n) => n.checked_add(1).map(|n| n.to_string()),
+            TagVersion::SemVer(mut v) => {
+                // A pre-release already at the bump's level, like `2.0.0-rc.1` for a major bump,
+                // is bumped to its release.
+                let released = v.is_prerelease()
+                    && match bump {
+                        Bump::Major => v.minor == 0 && v.patch == 0,
+                        Bump::Minor => v.patch == 0,
+                        Bump::Patch => true,
+                    };
+                let bumped_part = match bump {
+                    Bump::Major => v.major,
+                    Bump::Minor => v.minor,
+                    Bump::Patch => v.patch,
+                };
+                match bump {
+                    _ if released => {
+                        v.pre.clear();
+                        v.build.clear();
+                    }
+                    _ if bumped_part == u64::MAX => return None,
+                    Bump::Major => v.increment_major(),
+                    Bump::Minor => v.increment_minor(),
+                    Bump::Patch => v.increment_patch(),
+                }
+                Some(v.to_string())
+            }
+            TagVersion::Dotted(parts) => {
+                let last = parts.len() - 1;
+                let bumped = match bump {
+                    Bump::Major => 0,
+                    Bump::Minor => 1.min(last),
+                    Bump::Patch => last,
+                };
+                let widths = version.split('.').map(str::len);
+
+                parts
+                    .iter()
+                    .zip(widths)
+                    .enumerate()
+                    .map(|(i, (part, width))| {
+                        let part = match i.cmp(&bumped) {
+                            Ordering::Less => *part,
+                            Ordering::Equal => part.checked_add(1)?,
+                            Ordering::Greater => 0,
+                        };
+                        Some(format!("{:0width$}", part, width = width))
+                    })
+                    .collect::<Option<Vec<String>>>()
+                    .map(|parts| parts.join("."))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tags::{Bump, TagPattern, TagScheme, TagVersion};
+    use semver::Version;
+
+    const SEMVER: TagScheme = TagScheme::SemVer {
+        include_pre_releases: false,
+    };
+    const SEMVER_WITH_PRE: TagScheme = TagScheme::SemVer {
+        include_pre_releases: true,
+    };
+
+    #[test]
+    fn numeric_scheme_parses_integers_only() {
+        assert_eq!(
+            TagScheme::Numeric.parse("42"),
+            Some(TagVersion::Numeric(42))
+        );
+        assert_eq!(TagScheme::Numeric.parse("old"), None);
+        assert_eq!(TagScheme::Numeric.parse("1.2.3"), None);
+    }
+
+    #[test]
+    fn semver_scheme_parses_versions() {
+        assert_eq!(
+            SEMVER.parse("1.4.2"),
+            Some(TagVersion::SemVer(Version::new(1, 4, 2)))
+        );
+        assert_eq!(SEMVER.parse("old"), None);
+        assert_eq!(SEMVER.parse("42"), None);
+    }
+
+    #[test]
+    fn semver_scheme_can_exclude_pre_releases() {
+        assert_eq!(SEMVER.parse("1.5.0-rc.1"), None);
+        assert!(SEMVER_WITH_PRE.parse("1.5.0-rc.1").is_some());
+    }
+
+    #[test]
+    fn semver_versions_order_by_precedence() {
+        let pre = SEMVER_WITH_PRE.parse("1.5.0-rc.1").unwrap();
+        let release = SEMVER_WITH_PRE.parse("1.5.0").unwrap();
+        let older = SEMVER_WITH_PRE.parse("1.4.10").unwrap();
+
+        assert!(older < pre);
+        assert!(pre < release);
+    }
+
+    #[test]
+    fn unknown_scheme_name_is_an_error() {
+        assert_eq!(
+            TagScheme::from_name("roman", false)
+                .unwrap_err()
+                .to_string(),
+            "Unknown tag scheme: roman"
+        );
+    }
+
+    #[test]
+    fn dotted_scheme_orders_each_part_numerically() {
+        let older = TagScheme::Dotted.parse("2024.03.9").unwrap();
+        let newer = TagScheme::Dotted.parse("2024.03.10").unwrap();
+
+        assert!(older < newer);
+        assert_eq!(TagScheme::Dotted.parse("2024.x"), None);
+    }
+
+    #[test]
+    fn pattern_extracts_version_from_middle_of_tag() {
+        let pattern = TagPattern::new(r"release/(?P<version>[\d.]+)-payments").unwrap();
+
+        assert_eq!(
+            pattern.version("release/2024.03.1-payments"),
+            Some("2024.03.1")
+        );
+        assert_eq!(pattern.version("release/2024.03.1-billing"), None);
+        assert_eq!(pattern.version("old/release/2024.03.1-payments"), None);
+    }
+
+    #[test]
+    fn pattern_without_version_group_is_an_error() {
+        assert_eq!(
+            TagPattern::new("release/[\\d.]+").unwrap_err().to_string(),
+            "Tag pattern has no (?P<version>...) group: release/[\\d.]+"
+        );
+    }
+
+    #[test]
+    fn prefix_pattern_escapes_prefix() {
+        let pattern = TagPattern::from_prefix("v1.", &TagScheme::Numeric).unwrap();
+
+        assert_eq!(pattern.version("v1.2"), Some("2"));
+        assert_eq!(pattern.version("v1x2"), None);
+    }
+
+    #[test]
+    fn numeric_bump_always_adds_one() {
+        assert_eq!(
+            TagScheme::Numeric.bump("41", Bump::Patch),
+            Some("42".to_string())
+        );
+        assert_eq!(
+            TagScheme::Numeric.bump("41", Bump::Major),
+            Some("42".to_string())
+        );
+        assert_eq!(TagScheme::Numeric.bump("old", Bump::Patch), None);
+    }
+
+    #[test]
+    fn semver_bump_resets_lower_parts() {
+        assert_eq!(SEMVER.bump("1.4.2", Bump::Patch), Some("1.4.3".to_string()));
+        assert_eq!(SEMVER.bump("1.4.2", Bump::Minor), Some("1.5.0".to_string()));
+        assert_eq!(SEMVER.bump("1.4.2", Bump::Major), Some("2.0.0".to_string()));
+    }
+
+    #[test]
+    fn semver_patch_bump_of_pre_release_is_the_release() {
+        assert_eq!(
+            SEMVER_WITH_PRE.bump("1.5.0-rc.1", Bump::Patch),
+            Some("1.5.0".to_string())
+        );
+    }
+
+    #[test]
+    fn semver_bump_of_pre_release_at_that_level_is_the_release() {
+        let bump = |version, bump| SEMVER_WITH_PRE.bump(version, bump).unwrap();
+
+        assert_eq!(bump("1.5.0-rc.1", Bump::Minor), "1.5.0");
+        assert_eq!(bump("2.0.0-rc.1", Bump::Major), "2.0.0");
+        assert_eq!(bump("1.5.1-rc.1", Bump::Minor), "1.6.0");
+        assert_eq!(bump("2.1.0-rc.1", Bump::Major), "3.0.0");
+    }
+
+    #[test]
+    fn dotted_bump_keeps_zero_padding() {
+        let dotted = TagScheme::Dotted;
+
+        assert_eq!(
+            dotted.bump("2024.03.1", Bump::Patch),
+            Some("2024.03.2".to_string())
+        );
+        assert_eq!(
+            dotted.bump("2024.03.1", Bump::Minor),
+            Some("2024.04.0".to_string())
+        );
+        assert_eq!(
+            dotted.bump("2024.03.1", Bump::Major),
+            Some("2025.00.0".to_string())
+        );
+        assert_eq!(dotted.bump("7", Bump::Minor), Some("8".to_string()));
+    }
+
+    #[test]
+    fn bump_past_the_largest_version_is_none() {
+        let max = u64::MAX.to_string();
+
+        assert_eq!(TagScheme::Numeric.bump(max.as_str(), Bump::Patch), None);
+        assert_eq!(
+            SEMVER.bump(format!("1.2.{}", max).as_str(), Bump::Patch),
+            None
+        );
+        assert_eq!(
+            SEMVER.bump(format!("1.2.{}", max).as_str(), Bump::Minor),
+            Some("1.3.0".to_string())
+        );
+        assert_eq!(
+            TagScheme::Dotted.bump(format!("{}.1", max).as_str(), Bump::Major),
+            None
+        );
+    }
+
+    #[test]
+    fn unknown_bump_name_is_an_error() {
+        assert_eq!(
+            Bump::from_name("huge").unwrap_err().to_string(),
+            "Unknown version bump: huge"
+        );
+    }
+
+    #[test]
+    fn pattern_can_swap_version() {
+        let pattern = TagPattern::new(r"release/(?P<version>[\d.]+)-payments").unwrap();
+
+        assert_eq!(
+            pattern.with_version("release/2024.03.1-payments", "2024.03.2"),
+            Some("release/2024.03.2-payments".to_string())
+        );
+    }
+}